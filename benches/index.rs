@@ -0,0 +1,108 @@
+use std::collections::HashMap;
+
+use argo_helm_updater::helm::HelmRepoIndex;
+use chrono::{DateTime, Utc};
+use criterion::{criterion_group, criterion_main, Criterion};
+use serde::Deserialize;
+
+// the index as it was parsed before, keeping every annotation of every version
+#[derive(Deserialize)]
+#[allow(dead_code)]
+struct FullChartVersion {
+    #[serde(alias = "apiVersion")]
+    api_version: Option<String>,
+    name: String,
+    version: String,
+    #[serde(alias = "appVersion")]
+    app_version: Option<String>,
+    created: DateTime<Utc>,
+    #[serde(default)]
+    annotations: HashMap<String, String>,
+    #[serde(default)]
+    urls: Vec<String>,
+}
+
+#[derive(Deserialize)]
+#[allow(dead_code)]
+struct FullIndex {
+    #[serde(alias = "apiVersion")]
+    api_version: String,
+    entries: HashMap<String, Vec<FullChartVersion>>,
+}
+
+fn large_index(charts: usize, versions: usize) -> String {
+    let mut yaml = String::from("apiVersion: v1\nentries:\n");
+
+    for c in 0..charts {
+        yaml.push_str(&format!("  chart-{}:\n", c));
+
+        for v in 0..versions {
+            yaml.push_str(&format!(
+                "  - annotations:
+      artifacthub.io/changes: |
+        - kind: changed
+          description: Release {v} of chart {c}
+      images: |
+        - name: chart-{c}
+          image: registry.example.com/chart-{c}:{v}.0.0
+        - name: os-shell
+          image: registry.example.com/os-shell:12-debian-12-r{v}
+      licenses: Apache-2.0
+    apiVersion: v2
+    appVersion: {v}.0.0
+    created: \"2024-01-01T00:00:00.000000000Z\"
+    dependencies:
+    - name: common
+      repository: oci://registry.example.com/charts
+      version: 2.x.x
+    description: Synthetic chart {c} with a description as long as the ones of real world charts.
+    digest: 5f1d3c0e8a2b4d6f8091a2b3c4d5e6f708192a3b4c5d6e7f8091a2b3c4d5e6f7
+    home: https://example.com/charts/chart-{c}
+    icon: https://example.com/charts/chart-{c}/icon.svg
+    keywords:
+    - synthetic
+    - benchmark
+    maintainers:
+    - name: Example
+      url: https://github.com/example/charts
+    name: chart-{c}
+    sources:
+    - https://github.com/example/charts/tree/main/chart-{c}
+    urls:
+    - https://charts.example.com/chart-{c}-1.{v}.0.tgz
+    version: 1.{v}.0
+"
+            ));
+        }
+    }
+
+    yaml
+}
+
+fn parse_index(c: &mut Criterion) {
+    let yaml = large_index(200, 40);
+    let charts = vec!["chart-0".to_owned()];
+    let index = HelmRepoIndex::from_yaml(yaml.as_bytes(), &charts).expect("cannot parse index");
+    let cached = serde_json::to_vec(&index).expect("cannot serialize index");
+
+    let mut group = c.benchmark_group("parse index");
+    group.sample_size(10);
+
+    group.bench_function("full", |b| {
+        b.iter(|| serde_yaml::from_str::<FullIndex>(&yaml).expect("cannot parse index"))
+    });
+    group.bench_function("compact", |b| {
+        b.iter(|| serde_yaml::from_str::<HelmRepoIndex>(&yaml).expect("cannot parse index"))
+    });
+    group.bench_function("one chart", |b| {
+        b.iter(|| HelmRepoIndex::from_yaml(yaml.as_bytes(), &charts).expect("cannot parse index"))
+    });
+    group.bench_function("disk cache", |b| {
+        b.iter(|| serde_json::from_slice::<HelmRepoIndex>(&cached).expect("cannot parse cache"))
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, parse_index);
+criterion_main!(benches);
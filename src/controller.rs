@@ -0,0 +1,261 @@
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use anyhow::bail;
+use futures::{StreamExt, TryStreamExt};
+use kube::{
+    runtime::{reflector, watcher, WatchStreamExt},
+    Api, Client, ResourceExt,
+};
+use log::{debug, error, info};
+use tokio::sync::RwLock;
+
+use crate::{
+    check_application,
+    helm::{CachedHelmRepoClient, HelmRepoReqwestClient},
+    helm_repo_client,
+    kubernetes::{init_client, Application},
+    metrics::Metrics,
+    notifier,
+    notify::Notifier,
+    serve::serve_http,
+    Args,
+};
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct WatchArgs {
+    #[arg(
+        long,
+        default_value = "0.0.0.0:9090",
+        help = "Address to serve /metrics, /healthz and /readyz on"
+    )]
+    listen: SocketAddr,
+
+    #[arg(
+        long,
+        default_value_t = 3600,
+        help = "Seconds to cache a helm repository index before it is fetched again"
+    )]
+    cache_ttl: u64,
+}
+
+type HelmClient = CachedHelmRepoClient<HelmRepoReqwestClient>;
+
+fn application_key(application: &Application) -> String {
+    format!(
+        "{}/{}",
+        application.namespace().unwrap_or_default(),
+        application.name_any()
+    )
+}
+
+fn uses_repo(application: &Application, repos: &[String]) -> bool {
+    application.spec.helm_sources().iter().any(|(_, source)| {
+        source
+            .repo_url
+            .as_ref()
+            .is_some_and(|repo_url| repos.contains(repo_url))
+    })
+}
+
+async fn recheck(
+    client: &Client,
+    helm_client: &HelmClient,
+    application: &Application,
+    metrics: &RwLock<Metrics>,
+    write_back: bool,
+) {
+    debug!("checking application {}", application_key(application));
+
+    let report = check_application(client, helm_client, application, None, write_back).await;
+    let fetched = helm_client.take_fetched_repos();
+
+    metrics
+        .write()
+        .await
+        .record_application(application_key(application), report, &fetched);
+}
+
+fn is_changed(checked: &mut HashMap<String, Option<i64>>, application: &Application) -> bool {
+    // argo updates the status every few minutes, only a new generation changes the spec
+    let generation = application.metadata.generation;
+
+    checked.insert(application_key(application), generation) != Some(generation)
+}
+
+async fn notify_findings(metrics: &RwLock<Metrics>, notifier: &Notifier) {
+    if !notifier.is_enabled() {
+        return;
+    }
+
+    let report = metrics.read().await.findings.report();
+
+    if let Err(e) = notifier.notify(&report).await {
+        error!("cannot send notifications: {:?}", e);
+    }
+}
+
+async fn watch_applications(
+    client: Client,
+    helm_repo_client: HelmRepoReqwestClient,
+    cache_ttl: Duration,
+    metrics: Arc<RwLock<Metrics>>,
+    write_back: bool,
+    notifier: Notifier,
+) -> anyhow::Result<()> {
+    let helm_client = CachedHelmRepoClient::new(helm_repo_client, cache_ttl);
+    let api: Api<Application> = Api::default_namespaced(client.clone());
+
+    let (reader, writer) = reflector::store();
+    let mut events = reflector(writer, watcher(api, watcher::Config::default()))
+        .default_backoff()
+        .boxed();
+
+    let mut expiry = tokio::time::interval(
+        Duration::from_secs(60)
+            .min(cache_ttl)
+            .max(Duration::from_secs(1)),
+    );
+    let started_at = Instant::now();
+    let mut initialized = false;
+    let mut checked = HashMap::new();
+
+    loop {
+        tokio::select! {
+            event = events.try_next() => match event {
+                Ok(Some(watcher::Event::InitApply(app))) => {
+                    is_changed(&mut checked, &app);
+                }
+                Ok(Some(watcher::Event::Apply(app))) => {
+                    if !is_changed(&mut checked, &app) {
+                        continue;
+                    }
+
+                    recheck(&client, &helm_client, &app, &metrics, write_back).await;
+
+                    if initialized {
+                        notify_findings(&metrics, &notifier).await;
+                    }
+                }
+                Ok(Some(watcher::Event::Delete(app))) => {
+                    checked.remove(&application_key(&app));
+                    metrics.write().await.remove_application(&application_key(&app));
+                }
+                Ok(Some(watcher::Event::InitDone)) => {
+                    let apps = reader.state();
+
+                    // the charts of all applications are parsed from an index at once
+                    for (_, source) in apps.iter().flat_map(|a| a.spec.helm_sources()) {
+                        helm_client.expect_source(&source);
+                    }
+
+                    for app in &apps {
+                        recheck(&client, &helm_client, app, &metrics, write_back).await;
+                    }
+
+                    info!("checked {} application(s)", apps.len());
+                    metrics.write().await.record_sync(started_at.elapsed());
+
+                    initialized = true;
+                    notify_findings(&metrics, &notifier).await;
+                }
+                Ok(Some(watcher::Event::Init)) => {}
+                Ok(None) => bail!("application watch ended"),
+                Err(e) => error!("cannot watch applications: {:?}", e),
+            },
+            _ = expiry.tick() => {
+                let expired = helm_client.expired_repos();
+
+                if expired.is_empty() {
+                    continue;
+                }
+
+                debug!("helm repository index expired: {}", expired.join(", "));
+
+                let apps = reader.state();
+                let unused: Vec<String> = expired
+                    .iter()
+                    .filter(|repo| !apps.iter().any(|a| uses_repo(a, std::slice::from_ref(repo))))
+                    .cloned()
+                    .collect();
+                helm_client.evict_repos(&unused);
+
+                for app in apps.iter().filter(|a| uses_repo(a, &expired)) {
+                    recheck(&client, &helm_client, app, &metrics, write_back).await;
+                }
+
+                if initialized {
+                    notify_findings(&metrics, &notifier).await;
+                }
+            }
+        }
+    }
+}
+
+pub async fn watch(args: Args, watch_args: WatchArgs) -> anyhow::Result<()> {
+    if args.update || args.from_dir.is_some() {
+        bail!("--update and --from-dir cannot be used with watch");
+    }
+
+    let client = init_client(args.context.clone(), args.namespace.clone()).await?;
+    let helm_repo_client = helm_repo_client(&args, Some(&client)).await?;
+    let notifier = notifier(&args)?;
+    let metrics = Arc::new(RwLock::new(Metrics::default()));
+
+    tokio::select! {
+        result = watch_applications(
+            client,
+            helm_repo_client,
+            Duration::from_secs(watch_args.cache_ttl),
+            metrics.clone(),
+            args.write_back,
+            notifier,
+        ) => result,
+        result = serve_http(watch_args.listen, metrics) => result,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use serde_json::json;
+
+    use crate::kubernetes::Application;
+
+    use super::is_changed;
+
+    fn init_application(generation: i64, health: &str) -> Application {
+        serde_json::from_value(json!({
+            "apiVersion": "argoproj.io/v1alpha1",
+            "kind": "Application",
+            "metadata": { "name": "app", "namespace": "argocd", "generation": generation },
+            "spec": {
+                "project": "default",
+                "source": {
+                    "chart": "chart",
+                    "repoURL": "https://charts.example.com",
+                    "targetRevision": "1.1.0"
+                }
+            },
+            "status": { "health": { "status": health } },
+        }))
+        .expect("invalid application")
+    }
+
+    #[test]
+    fn is_changed_ignores_status_updates() {
+        let mut checked = HashMap::new();
+
+        assert!(is_changed(
+            &mut checked,
+            &init_application(1, "Progressing")
+        ));
+        assert!(!is_changed(&mut checked, &init_application(1, "Healthy")));
+        assert!(is_changed(&mut checked, &init_application(2, "Healthy")));
+    }
+}
@@ -0,0 +1,48 @@
+use thiserror::Error;
+
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum CheckError {
+    #[error("chart {chart} cannot be found in the repository index")]
+    ChartNotFound { chart: String },
+
+    #[error("chart {chart} has no released version")]
+    NoReleasedVersion { chart: String },
+
+    #[error("helm repository {url} is unreachable{}: {reason}", status.map(|s| format!(" (status {})", s)).unwrap_or_default())]
+    RepoUnreachable {
+        url: String,
+        status: Option<u16>,
+        reason: String,
+    },
+
+    #[error("helm repository {url} requires authentication (status {status})")]
+    AuthRequired { url: String, status: u16 },
+
+    #[error("cannot parse index of {url}{}: {message}", line.zip(*column).map(|(l, c)| format!(" at line {}, column {}", l, c)).unwrap_or_default())]
+    IndexParse {
+        url: String,
+        line: Option<usize>,
+        column: Option<usize>,
+        message: String,
+    },
+
+    #[error("unsupported source: {reason}")]
+    UnsupportedSource { reason: String },
+
+    #[error("cannot access {resource}: {message}")]
+    Kubernetes { resource: String, message: String },
+}
+
+impl CheckError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            CheckError::ChartNotFound { .. } => "chart-not-found",
+            CheckError::NoReleasedVersion { .. } => "no-released-version",
+            CheckError::RepoUnreachable { .. } => "repo-unreachable",
+            CheckError::AuthRequired { .. } => "auth-required",
+            CheckError::IndexParse { .. } => "index-parse-error",
+            CheckError::UnsupportedSource { .. } => "unsupported-source",
+            CheckError::Kubernetes { .. } => "kubernetes-error",
+        }
+    }
+}
@@ -0,0 +1,367 @@
+use std::{path::Path, process::Command};
+
+use anyhow::bail;
+use clap::ValueEnum;
+use log::{debug, error, info};
+
+use crate::{manifest::update_manifest, report::Finding};
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum CommitMode {
+    PerUpdate,
+    Grouped,
+}
+
+#[derive(Debug, Clone)]
+pub struct GitOptions {
+    pub mode: CommitMode,
+    pub branch_template: String,
+    pub group_branch: String,
+    pub message_template: String,
+}
+
+pub fn render_template(template: &str, finding: &Finding) -> String {
+    let (new, changelog) = match finding.update() {
+        Some(update) => (
+            update.version.clone(),
+            update
+                .changelog
+                .iter()
+                .map(|c| c.to_string())
+                .collect::<Vec<String>>()
+                .join("\n"),
+        ),
+        None => (String::new(), String::new()),
+    };
+
+    template
+        .replace("{app}", &finding.application)
+        .replace("{namespace}", &finding.namespace)
+        .replace("{chart}", &finding.chart)
+        .replace("{old}", &finding.current)
+        .replace("{new}", &new)
+        .replace("{changelog}", &changelog)
+        .trim_end()
+        .to_string()
+}
+
+fn git(repo: &Path, args: &[&str]) -> anyhow::Result<String> {
+    debug!("git {}", args.join(" "));
+
+    let output = Command::new("git").current_dir(repo).args(args).output()?;
+
+    if !output.status.success() {
+        bail!(
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+fn commit_files(
+    repo: &Path,
+    findings: &[&Finding],
+    message: &str,
+    refresh_lock: bool,
+) -> anyhow::Result<()> {
+    for finding in findings {
+        let location = finding.location.as_ref().unwrap();
+        let new_version = &finding.update().unwrap().version;
+
+        for path in update_manifest(location, new_version, refresh_lock)? {
+            let path = path.canonicalize()?;
+            git(repo, &["add", "--", &path.to_string_lossy()])?;
+        }
+    }
+
+    git(repo, &["commit", "--quiet", "-m", message])?;
+
+    Ok(())
+}
+
+fn commit_on_branch(
+    repo: &Path,
+    base: &str,
+    branch: &str,
+    findings: &[&Finding],
+    message: &str,
+    refresh_lock: bool,
+) -> anyhow::Result<()> {
+    let previous = git(
+        repo,
+        &[
+            "rev-parse",
+            "--verify",
+            "--quiet",
+            &format!("refs/heads/{}", branch),
+        ],
+    )
+    .ok();
+
+    // the branch of a previous run is reset, so a rerun does not stack a second update on it
+    git(repo, &["checkout", "--quiet", "-B", branch, base])?;
+
+    let result = commit_files(repo, findings, message, refresh_lock);
+
+    if result.is_err() {
+        git(repo, &["reset", "--quiet", "--hard"])?;
+    }
+
+    git(repo, &["checkout", "--quiet", base])?;
+
+    if result.is_err() {
+        match &previous {
+            Some(revision) => git(repo, &["branch", "--quiet", "-f", branch, revision])?,
+            None => git(repo, &["branch", "--quiet", "-D", branch])?,
+        };
+    }
+
+    result
+}
+
+pub fn commit_updates(
+    repo: &Path,
+    findings: &[Finding],
+    options: &GitOptions,
+    refresh_lock: bool,
+) -> anyhow::Result<Vec<String>> {
+    let findings: Vec<&Finding> = findings
+        .iter()
+        .filter(|f| f.location.is_some() && f.update().is_some())
+        .collect();
+
+    if findings.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    if !git(repo, &["status", "--porcelain", "--untracked-files=no"])?.is_empty() {
+        bail!(
+            "the working tree of {} has uncommitted changes",
+            repo.display()
+        );
+    }
+
+    // a detached checkout, as in most CI pipelines, has no branch to return to
+    let base = match git(repo, &["rev-parse", "--abbrev-ref", "HEAD"])?.as_str() {
+        "HEAD" => git(repo, &["rev-parse", "HEAD"])?,
+        branch => branch.to_string(),
+    };
+    let mut branches = Vec::new();
+
+    match options.mode {
+        CommitMode::PerUpdate => {
+            for finding in findings {
+                let branch = render_template(&options.branch_template, finding);
+                let message = render_template(&options.message_template, finding);
+
+                match commit_on_branch(repo, &base, &branch, &[finding], &message, refresh_lock) {
+                    Ok(()) => {
+                        info!("committed update of {} to branch {}", finding.chart, branch);
+                        branches.push(branch);
+                    }
+                    Err(e) => error!("cannot commit update to branch {}: {:?}", branch, e),
+                }
+            }
+        }
+        CommitMode::Grouped => {
+            let message = format!(
+                "Update {} helm chart(s)\n\n{}",
+                findings.len(),
+                findings
+                    .iter()
+                    .map(|f| render_template(&options.message_template, f))
+                    .collect::<Vec<String>>()
+                    .join("\n\n")
+            );
+
+            commit_on_branch(
+                repo,
+                &base,
+                &options.group_branch,
+                &findings,
+                &message,
+                refresh_lock,
+            )?;
+
+            info!(
+                "committed {} update(s) to branch {}",
+                findings.len(),
+                options.group_branch
+            );
+            branches.push(options.group_branch.clone());
+        }
+    }
+
+    Ok(branches)
+}
+
+#[cfg(test)]
+mod test {
+    use std::{fs, path::Path};
+
+    use crate::{
+        helm::{ChangelogEntry, ChartUpdate},
+        manifest::{target_revision_path, ManifestLocation},
+        report::{Finding, FindingKind},
+        yaml::PathSegment,
+    };
+
+    use super::{commit_updates, git, render_template, CommitMode, GitOptions};
+
+    fn init_update(repo: &Path, application: &str, yaml_path: Vec<PathSegment>) -> Finding {
+        Finding {
+            resource_kind: "Application".to_owned(),
+            namespace: "argocd".to_owned(),
+            application: application.to_owned(),
+            chart: "loki".to_owned(),
+            repo: "https://grafana.github.io/helm-charts".to_owned(),
+            current: "5.0.0".to_owned(),
+            kind: FindingKind::UpdateAvailable(ChartUpdate {
+                version: "5.1.0".to_owned(),
+                ..Default::default()
+            }),
+            owner: None,
+            location: Some(ManifestLocation {
+                path: repo.join(format!("{}.yaml", application)),
+                document: 0,
+                yaml_path,
+            }),
+        }
+    }
+
+    #[test]
+    fn render_template_with_changelog() {
+        let finding = Finding {
+            resource_kind: "Application".to_owned(),
+            namespace: "argocd".to_owned(),
+            application: "loki".to_owned(),
+            chart: "loki".to_owned(),
+            repo: "https://grafana.github.io/helm-charts".to_owned(),
+            current: "5.0.0".to_owned(),
+            kind: FindingKind::UpdateAvailable(ChartUpdate {
+                version: "5.1.0".to_owned(),
+                changelog: vec![ChangelogEntry {
+                    version: "5.1.0".to_owned(),
+                    kind: Some("fixed".to_owned()),
+                    description: "retention".to_owned(),
+                }],
+                ..Default::default()
+            }),
+            owner: None,
+            location: None,
+        };
+
+        let result = render_template(
+            "Update {chart} of {app} from {old} to {new}\n\n{changelog}",
+            &finding,
+        );
+
+        assert_eq!(
+            "Update loki of loki from 5.0.0 to 5.1.0\n\n- 5.1.0 (fixed): retention",
+            result
+        );
+    }
+
+    fn init_repo(repo: &Path, applications: &[&str]) {
+        fs::create_dir_all(repo).unwrap();
+
+        for application in applications {
+            fs::write(
+                repo.join(format!("{}.yaml", application)),
+                "spec:\n  source:\n    chart: loki\n    targetRevision: 5.0.0\n",
+            )
+            .unwrap();
+        }
+
+        for args in [
+            &["init", "--quiet"][..],
+            &["config", "user.name", "test"],
+            &["config", "user.email", "test@example.com"],
+            &["config", "commit.gpgsign", "false"],
+            &["add", "."],
+            &["commit", "--quiet", "-m", "initial"],
+            &["checkout", "--quiet", "--detach"],
+        ] {
+            git(repo, args).unwrap();
+        }
+    }
+
+    #[test]
+    fn commit_updates_per_update_from_detached_head() {
+        let dir = tempfile::tempdir().expect("cannot create temp dir");
+        let repo = dir.path();
+        init_repo(repo, &["loki", "logs", "broken"]);
+
+        let base = git(repo, &["rev-parse", "HEAD"]).unwrap();
+
+        // broken has no sources, so its update fails
+        let findings = vec![
+            init_update(repo, "loki", target_revision_path(None)),
+            init_update(repo, "broken", target_revision_path(Some(0))),
+            init_update(repo, "logs", target_revision_path(None)),
+        ];
+
+        let options = GitOptions {
+            mode: CommitMode::PerUpdate,
+            branch_template: "update/{app}".to_owned(),
+            group_branch: String::new(),
+            message_template: "Update {chart} of {app}".to_owned(),
+        };
+
+        let branches = commit_updates(repo, &findings, &options, false).unwrap();
+
+        assert_eq!(vec!["update/loki", "update/logs"], branches);
+        assert_eq!(base, git(repo, &["rev-parse", "HEAD"]).unwrap());
+        assert_eq!(
+            "loki.yaml",
+            git(repo, &["diff", "--name-only", &base, "update/loki"]).unwrap()
+        );
+        assert_eq!(
+            "logs.yaml",
+            git(repo, &["diff", "--name-only", &base, "update/logs"]).unwrap()
+        );
+        assert!(git(repo, &["rev-parse", "--verify", "update/broken"]).is_err());
+        assert!(git(repo, &["status", "--porcelain"]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn commit_updates_grouped_twice_resets_branch() {
+        let dir = tempfile::tempdir().expect("cannot create temp dir");
+        let repo = dir.path();
+        init_repo(repo, &["loki", "logs"]);
+
+        let base = git(repo, &["rev-parse", "HEAD"]).unwrap();
+        let findings = vec![
+            init_update(repo, "loki", target_revision_path(None)),
+            init_update(repo, "logs", target_revision_path(None)),
+        ];
+
+        let options = GitOptions {
+            mode: CommitMode::Grouped,
+            branch_template: String::new(),
+            group_branch: "update/charts".to_owned(),
+            message_template: "Update {chart} of {app}".to_owned(),
+        };
+
+        for _ in 0..2 {
+            assert_eq!(
+                vec!["update/charts"],
+                commit_updates(repo, &findings, &options, false).unwrap()
+            );
+        }
+
+        assert_eq!(base, git(repo, &["rev-parse", "HEAD"]).unwrap());
+        assert_eq!(
+            "1",
+            git(
+                repo,
+                &["rev-list", "--count", &format!("{}..update/charts", base)]
+            )
+            .unwrap()
+        );
+        assert!(git(repo, &["status", "--porcelain"]).unwrap().is_empty());
+    }
+}
@@ -1,14 +1,37 @@
-use std::{cmp::Ordering, collections::HashMap};
-
-use anyhow::{bail, Ok};
+use std::{
+    cmp::Ordering,
+    collections::{HashMap, HashSet},
+    fmt, fs,
+    io::Read,
+    path::PathBuf,
+    str::FromStr,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use anyhow::anyhow;
 use async_trait::async_trait;
 use chrono::{DateTime, Utc};
-use log::debug;
+use flate2::read::GzDecoder;
+use log::{debug, warn};
 use mockall::{predicate::*, *};
-use serde::Deserialize;
-use serde_yaml::Error;
-
-use crate::kubernetes::SourceSpec;
+use reqwest::{
+    header::{self, HeaderMap},
+    StatusCode,
+};
+use serde::{
+    de::{self, DeserializeSeed, IgnoredAny, MapAccess, Visitor},
+    Deserialize, Deserializer, Serialize,
+};
+use serde_yaml::{Error, Value};
+use url::Url;
+
+use crate::{
+    error::CheckError,
+    kubernetes::{SourceSpec, SyncReport},
+};
+
+const CHANGES_ANNOTATION: &str = "artifacthub.io/changes";
 
 #[derive(Debug)]
 pub struct HelmChart {
@@ -17,20 +40,38 @@ pub struct HelmChart {
     pub revision: String,
 }
 
+fn unsupported_source(reason: &str) -> CheckError {
+    CheckError::UnsupportedSource {
+        reason: reason.to_string(),
+    }
+}
+
 impl TryFrom<SourceSpec> for HelmChart {
-    type Error = anyhow::Error;
+    type Error = CheckError;
 
     fn try_from(value: SourceSpec) -> Result<Self, Self::Error> {
         if value.chart.is_none() {
-            bail!("missing chart");
+            return Err(unsupported_source("missing chart"));
         }
 
         if value.repo_url.is_none() {
-            bail!("missing repo_url");
+            return Err(unsupported_source("missing repo_url"));
         }
 
         if value.target_revision.is_none() {
-            bail!("missing target_revision url");
+            return Err(unsupported_source("missing target_revision"));
+        }
+
+        let templated = [
+            ("chart", &value.chart),
+            ("repo_url", &value.repo_url),
+            ("target_revision", &value.target_revision),
+        ]
+        .into_iter()
+        .find(|(_, field)| field.as_ref().unwrap().contains("{{"));
+
+        if let Some((name, _)) = templated {
+            return Err(unsupported_source(&format!("templated {}", name)));
         }
 
         Ok(Self {
@@ -41,58 +82,353 @@ impl TryFrom<SourceSpec> for HelmChart {
     }
 }
 
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChangelogEntry {
+    pub version: String,
+    pub kind: Option<String>,
+    pub description: String,
+}
+
+impl fmt::Display for ChangelogEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            Some(kind) => write!(f, "- {} ({}): {}", self.version, kind, self.description),
+            None => write!(f, "- {}: {}", self.version, self.description),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ChartUpdate {
+    pub version: String,
+    pub changelog: Vec<ChangelogEntry>,
+    pub current_app_version: Option<String>,
+    pub app_version: Option<String>,
+    pub released: Option<DateTime<Utc>>,
+    pub url: Option<String>,
+    pub removed_values: Vec<String>,
+    pub sync: Option<SyncReport>,
+}
+
 impl HelmChart {
     pub async fn get_newer_version(
         &self,
         client: &dyn HelmRepoClient,
     ) -> anyhow::Result<Option<String>> {
-        let newest_version = client
-            .get_helm_repo_index(&self.repo)
-            .await?
-            .get_newest_chart_version(&self.chart)?;
+        Ok(self.get_chart_update(client).await?.map(|u| u.version))
+    }
 
-        match self.revision.cmp(&newest_version) {
-            Ordering::Greater => Ok(Some(newest_version)),
-            Ordering::Equal => Ok(None),
-            Ordering::Less => Ok(Some(newest_version)),
+    pub async fn get_chart_update(
+        &self,
+        client: &dyn HelmRepoClient,
+    ) -> anyhow::Result<Option<ChartUpdate>> {
+        let index = client
+            .get_helm_repo_index(&self.repo, std::slice::from_ref(&self.chart))
+            .await?;
+        let newest_version = index.get_newest_chart_version(&self.chart)?;
+
+        // a target ahead of the index, e.g. a yanked or not yet indexed version, is no downgrade to suggest
+        match compare_versions(&self.revision, &newest_version) {
+            Ordering::Equal | Ordering::Greater => Ok(None),
+            Ordering::Less => {
+                let current = index.get_chart_version(&self.chart, &self.revision);
+                let newest = index.get_chart_version(&self.chart, &newest_version);
+
+                Ok(Some(ChartUpdate {
+                    changelog: index.get_changelog(&self.chart, &self.revision, &newest_version),
+                    current_app_version: current.and_then(|v| v.app_version.clone()),
+                    app_version: newest.and_then(|v| v.app_version.clone()),
+                    released: newest.and_then(|v| v.created),
+                    url: newest.and_then(|v| v.urls.first().map(|url| without_query(url))),
+                    version: newest_version,
+                    removed_values: Vec::new(),
+                    sync: None,
+                }))
+            }
         }
     }
+
+    pub async fn get_removed_values(
+        &self,
+        client: &dyn HelmRepoClient,
+        newest_version: &str,
+        values: &Value,
+    ) -> anyhow::Result<Vec<String>> {
+        let index = client
+            .get_helm_repo_index(&self.repo, std::slice::from_ref(&self.chart))
+            .await?;
+
+        let chart_url = |version: &str| {
+            index
+                .get_chart_version(&self.chart, version)
+                .and_then(|v| v.urls.first().cloned())
+                .ok_or_else(|| anyhow!("chart {} {} has no download url", self.chart, version))
+        };
+
+        let current = client.get_chart_values(&chart_url(&self.revision)?).await?;
+        let newest = client.get_chart_values(&chart_url(newest_version)?).await?;
+
+        let mut removed = Vec::new();
+        removed_values(values, &current, &newest, "", &mut removed);
+
+        Ok(removed)
+    }
+}
+
+fn chart_values(archive: &[u8]) -> anyhow::Result<Value> {
+    let mut archive = tar::Archive::new(GzDecoder::new(archive));
+
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.into_owned();
+
+        // <chart>/values.yaml, not the values of bundled subcharts
+        if path.components().count() == 2 && path.ends_with("values.yaml") {
+            let mut content = String::new();
+            entry.read_to_string(&mut content)?;
+
+            return Ok(serde_yaml::from_str(&content)?);
+        }
+    }
+
+    Ok(Value::Mapping(Default::default()))
+}
+
+// keys set in the values that the current chart defines by default, but the newest does not
+pub fn removed_values(
+    values: &Value,
+    current: &Value,
+    newest: &Value,
+    prefix: &str,
+    removed: &mut Vec<String>,
+) {
+    let values = match values.as_mapping() {
+        Some(values) => values,
+        None => return,
+    };
+
+    for (key, value) in values {
+        let (name, current) = match (key.as_str(), current.as_mapping().and_then(|m| m.get(key))) {
+            (Some(name), Some(current)) => (name, current),
+            _ => continue,
+        };
+
+        let path = match prefix.is_empty() {
+            true => name.to_string(),
+            false => format!("{}.{}", prefix, name),
+        };
+
+        match newest.as_mapping().and_then(|m| m.get(key)) {
+            Some(newest) => removed_values(value, current, newest, &path, removed),
+            None => removed.push(path),
+        }
+    }
+}
+
+pub fn parse_semver(version: &str) -> Option<versions::SemVer> {
+    versions::SemVer::new(version.strip_prefix('v').unwrap_or(version))
+}
+
+fn compare_versions(a: &str, b: &str) -> Ordering {
+    match (parse_semver(a), parse_semver(b)) {
+        (Some(a), Some(b)) => a.cmp(&b),
+        _ => a.cmp(b),
+    }
 }
 
-#[derive(Deserialize, Debug, Clone, PartialEq)]
+fn parse_changes(version: &str, changes: &str) -> Vec<ChangelogEntry> {
+    let values: Vec<serde_yaml::Value> = match serde_yaml::from_str(changes) {
+        Ok(v) => v,
+        Err(e) => {
+            debug!("cannot parse changes of {}: {:?}", version, e);
+
+            return Vec::new();
+        }
+    };
+
+    values
+        .iter()
+        .filter_map(|v| match v {
+            serde_yaml::Value::String(description) => Some(ChangelogEntry {
+                version: version.to_string(),
+                kind: None,
+                description: description.clone(),
+            }),
+            serde_yaml::Value::Mapping(_) => Some(ChangelogEntry {
+                version: version.to_string(),
+                kind: v.get("kind").and_then(|k| k.as_str()).map(str::to_string),
+                description: v.get("description")?.as_str()?.to_string(),
+            }),
+            _ => None,
+        })
+        .collect()
+}
+
+struct ChangesAnnotationVisitor;
+
+impl<'de> Visitor<'de> for ChangesAnnotationVisitor {
+    type Value = HashMap<String, String>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a map of annotations")
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<Self::Value, E> {
+        Ok(HashMap::new())
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut annotations = HashMap::new();
+
+        while let Some(key) = map.next_key::<String>()? {
+            if key == CHANGES_ANNOTATION {
+                annotations.insert(key, map.next_value()?);
+            } else {
+                map.next_value::<IgnoredAny>()?;
+            }
+        }
+
+        Ok(annotations)
+    }
+}
+
+// indexes like bitnami's carry large annotations per version, only the changelog is kept
+fn deserialize_changes_annotation<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<HashMap<String, String>, D::Error> {
+    deserializer.deserialize_any(ChangesAnnotationVisitor)
+}
+
+// only the fields needed for the checks are kept, everything else is skipped while parsing
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct HelmRepoChartVersion {
-    #[serde(alias = "apiVersion")]
-    pub api_version: Option<String>,
-    pub name: String,
     pub version: String,
-    pub created: DateTime<Utc>,
+    #[serde(alias = "appVersion")]
+    pub app_version: Option<String>,
+    #[serde(default)]
+    pub created: Option<DateTime<Utc>>,
+    #[serde(default, deserialize_with = "deserialize_changes_annotation")]
+    pub annotations: HashMap<String, String>,
+    #[serde(default)]
+    pub urls: Vec<String>,
 }
 
-#[derive(Deserialize, Debug, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct HelmRepoIndex {
     #[serde(alias = "apiVersion")]
     pub api_version: String,
     pub entries: HashMap<String, Vec<HelmRepoChartVersion>>,
+    // the charts an index was parsed for, all of them when none
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub charts: Option<HashSet<String>>,
+}
+
+struct ChartEntriesVisitor<'a> {
+    charts: &'a [String],
+}
+
+impl<'de> Visitor<'de> for ChartEntriesVisitor<'_> {
+    type Value = HashMap<String, Vec<HelmRepoChartVersion>>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a map of charts")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut entries = HashMap::new();
+
+        while let Some(key) = map.next_key::<String>()? {
+            if self.charts.contains(&key) {
+                entries.insert(key, map.next_value()?);
+            } else {
+                map.next_value::<IgnoredAny>()?;
+            }
+        }
+
+        Ok(entries)
+    }
+}
+
+impl<'de> DeserializeSeed<'de> for ChartEntriesVisitor<'_> {
+    type Value = HashMap<String, Vec<HelmRepoChartVersion>>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+struct ChartIndexVisitor<'a> {
+    charts: &'a [String],
+}
+
+impl<'de> Visitor<'de> for ChartIndexVisitor<'_> {
+    type Value = HelmRepoIndex;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a helm repository index")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut api_version = None;
+        let mut entries = None;
+
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "apiVersion" | "api_version" => api_version = Some(map.next_value()?),
+                "entries" => {
+                    entries = Some(map.next_value_seed(ChartEntriesVisitor {
+                        charts: self.charts,
+                    })?)
+                }
+                _ => {
+                    map.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+
+        Ok(HelmRepoIndex {
+            api_version: api_version.ok_or_else(|| de::Error::missing_field("apiVersion"))?,
+            entries: entries.ok_or_else(|| de::Error::missing_field("entries"))?,
+            charts: Some(self.charts.iter().cloned().collect()),
+        })
+    }
 }
 
 impl HelmRepoIndex {
-    pub fn get_newest_chart_version(&self, chart_name: &str) -> anyhow::Result<String> {
+    // indexes like bitnami's list thousands of charts, only the entries of the checked ones are parsed
+    pub fn from_yaml(content: &[u8], charts: &[String]) -> Result<Self, Error> {
+        serde_yaml::Deserializer::from_slice(content).deserialize_map(ChartIndexVisitor { charts })
+    }
+
+    pub fn has_chart(&self, chart_name: &str) -> bool {
+        self.charts
+            .as_ref()
+            .is_none_or(|charts| charts.contains(chart_name))
+    }
+
+    fn resolve_urls(&mut self, repo_url: &str) {
+        for version in self.entries.values_mut().flatten() {
+            for url in version.urls.iter_mut() {
+                if let Ok(resolved) = join_url(repo_url, url) {
+                    *url = resolved.to_string();
+                }
+            }
+        }
+    }
+
+    pub fn get_newest_chart_version(&self, chart_name: &str) -> Result<String, CheckError> {
         let versions = self.entries.get(chart_name);
 
         if versions.is_none() {
-            bail!("cannot find chart");
+            return Err(CheckError::ChartNotFound {
+                chart: chart_name.to_string(),
+            });
         }
 
         let mut semvers: Vec<_> = versions
             .unwrap()
             .iter()
-            .filter_map(|v| {
-                let mut ver = v.version.clone();
-                if v.version.starts_with('v') {
-                    ver = ver[1..].to_string();
-                }
-                versions::SemVer::new(&ver).map(|sv| (sv, v.version.clone()))
-            })
+            .filter_map(|v| parse_semver(&v.version).map(|sv| (sv, v.version.clone())))
             .filter(|(v, _)| v.pre_rel.is_none())
             .collect();
 
@@ -101,53 +437,664 @@ impl HelmRepoIndex {
 
         match semvers.first() {
             Some(version) => Ok(version.1.to_string()),
-            None => bail!("cannot get newest version"),
+            None => Err(CheckError::NoReleasedVersion {
+                chart: chart_name.to_string(),
+            }),
         }
     }
+
+    pub fn get_chart_version(
+        &self,
+        chart_name: &str,
+        version: &str,
+    ) -> Option<&HelmRepoChartVersion> {
+        self.entries
+            .get(chart_name)?
+            .iter()
+            .find(|v| v.version == version)
+    }
+
+    pub fn get_changelog(&self, chart_name: &str, from: &str, to: &str) -> Vec<ChangelogEntry> {
+        let versions = match self.entries.get(chart_name) {
+            Some(versions) => versions,
+            None => return Vec::new(),
+        };
+
+        let from = parse_semver(from);
+        let to = parse_semver(to);
+
+        let mut changes: Vec<_> = versions
+            .iter()
+            .filter_map(|v| parse_semver(&v.version).map(|sv| (sv, v)))
+            .filter(|(sv, _)| from.as_ref().is_none_or(|from| sv > from))
+            .filter(|(sv, _)| to.as_ref().is_none_or(|to| sv <= to))
+            .filter(|(sv, _)| sv.pre_rel.is_none())
+            .collect();
+
+        changes.sort_by(|a, b| b.0.cmp(&a.0));
+
+        changes
+            .iter()
+            .filter_map(|(_, v)| {
+                v.annotations
+                    .get(CHANGES_ANNOTATION)
+                    .map(|changes| parse_changes(&v.version, changes))
+            })
+            .flatten()
+            .collect()
+    }
 }
 
 #[automock]
 #[async_trait]
-pub trait HelmRepoClient {
-    async fn get_helm_repo_index(&self, repo_url: &str) -> anyhow::Result<HelmRepoIndex>;
+pub trait HelmRepoClient: Send + Sync {
+    async fn get_helm_repo_index(
+        &self,
+        repo_url: &str,
+        charts: &[String],
+    ) -> anyhow::Result<HelmRepoIndex>;
+    async fn get_chart_values(&self, chart_url: &str) -> anyhow::Result<Value>;
+}
+
+const INDEX_PATH: &str = "index.yaml";
+
+// signed urls carry their token in the query, which must not end up in a report
+pub fn without_query(url: &str) -> String {
+    match Url::parse(url) {
+        Ok(mut url) => {
+            url.set_query(None);
+            url.to_string()
+        }
+        Err(_) => url.split('?').next().unwrap_or(url).to_string(),
+    }
+}
+
+pub fn join_url(base: &str, path: &str) -> Result<Url, url::ParseError> {
+    let mut base = Url::parse(base)?;
+
+    if !base.path().ends_with('/') {
+        base.set_path(&format!("{}/", base.path()));
+    }
+
+    let mut joined = base.join(path)?;
+
+    // keep tokens of signed repository urls on everything served by the repository
+    if joined.query().is_none() && joined.origin() == base.origin() {
+        joined.set_query(base.query());
+    }
+
+    Ok(joined)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexPath {
+    pub repo_url: String,
+    pub path: String,
+}
+
+impl FromStr for IndexPath {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.rsplit_once('=') {
+            Some((repo_url, path)) if !repo_url.is_empty() && !path.is_empty() => Ok(Self {
+                repo_url: repo_url.to_string(),
+                path: path.to_string(),
+            }),
+            _ => anyhow::bail!("expected <repoURL>=<path>, got '{}'", s),
+        }
+    }
+}
+
+const RETRY_BACKOFF: Duration = Duration::from_millis(500);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClientCert {
+    pub cert: String,
+    pub key: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RepoOptions {
+    pub url: String,
+    pub insecure: bool,
+    pub client_cert: Option<ClientCert>,
+}
+
+#[derive(Debug, Clone)]
+pub struct HttpOptions {
+    pub connect_timeout: Duration,
+    pub read_timeout: Duration,
+    pub retries: u32,
+    pub ca_certs: Vec<String>,
+    pub client_cert: Option<ClientCert>,
+    pub proxy: Option<String>,
+    pub no_proxy: Option<String>,
+    pub repos: Vec<RepoOptions>,
+    pub index_paths: Vec<IndexPath>,
+    pub cache_dir: Option<PathBuf>,
+}
+
+impl HttpOptions {
+    fn build_client(
+        &self,
+        client_cert: Option<&ClientCert>,
+        insecure: bool,
+    ) -> anyhow::Result<reqwest::Client> {
+        let mut builder = reqwest::Client::builder()
+            .user_agent(concat!("argo-helm-updater/", env!("CARGO_PKG_VERSION")))
+            .connect_timeout(self.connect_timeout)
+            .read_timeout(self.read_timeout)
+            .danger_accept_invalid_certs(insecure)
+            .danger_accept_invalid_hostnames(insecure);
+
+        for bundle in &self.ca_certs {
+            for cert in reqwest::Certificate::from_pem_bundle(bundle.as_bytes())? {
+                builder = builder.add_root_certificate(cert);
+            }
+        }
+
+        if let Some(client_cert) = client_cert {
+            let key = openssl::pkey::PKey::private_key_from_pem(client_cert.key.as_bytes())?
+                .private_key_to_pem_pkcs8()?;
+
+            builder = builder.identity(reqwest::Identity::from_pkcs8_pem(
+                client_cert.cert.as_bytes(),
+                &key,
+            )?);
+        }
+
+        if let Some(proxy) = &self.proxy {
+            let no_proxy = self
+                .no_proxy
+                .as_deref()
+                .and_then(reqwest::NoProxy::from_string);
+
+            builder = builder.proxy(reqwest::Proxy::all(proxy)?.no_proxy(no_proxy));
+        }
+
+        Ok(builder.build()?)
+    }
+}
+
+pub struct HelmRepoReqwestClient {
+    client: reqwest::Client,
+    repo_clients: Vec<(String, reqwest::Client)>,
+    index_paths: Vec<IndexPath>,
+    cache_dir: Option<PathBuf>,
+    retries: u32,
+    backoff: Duration,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct CachedIndex {
+    etag: Option<String>,
+    last_modified: Option<String>,
+    index: HelmRepoIndex,
+}
+
+impl HelmRepoReqwestClient {
+    pub fn new(options: &HttpOptions) -> anyhow::Result<Self> {
+        let client = options.build_client(options.client_cert.as_ref(), false)?;
+
+        let mut repo_clients = Vec::new();
+        for repo in &options.repos {
+            let client_cert = repo.client_cert.as_ref().or(options.client_cert.as_ref());
+
+            match options.build_client(client_cert, repo.insecure) {
+                Ok(client) => {
+                    repo_clients.push((repo.url.trim_end_matches('/').to_string(), client))
+                }
+                Err(e) => warn!("skipping tls settings of repository {}: {:#}", repo.url, e),
+            }
+        }
+
+        // the most specific repository prefix wins
+        repo_clients.sort_by_key(|(url, _)| std::cmp::Reverse(url.len()));
+
+        Ok(Self {
+            client,
+            repo_clients,
+            index_paths: options.index_paths.clone(),
+            cache_dir: options.cache_dir.clone(),
+            retries: options.retries,
+            backoff: RETRY_BACKOFF,
+        })
+    }
+
+    fn index_url(&self, repo_url: &str) -> Result<Url, CheckError> {
+        let path = self
+            .index_paths
+            .iter()
+            .find(|i| i.repo_url.trim_end_matches('/') == repo_url.trim_end_matches('/'))
+            .map(|i| i.path.as_str())
+            .unwrap_or(INDEX_PATH);
+
+        join_url(repo_url, path)
+            .map_err(|e| unsupported_source(&format!("invalid repository url {}: {}", repo_url, e)))
+    }
+
+    fn client_for(&self, url: &str) -> &reqwest::Client {
+        self.repo_clients
+            .iter()
+            .find(|(prefix, _)| {
+                url.strip_prefix(prefix.as_str())
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+            })
+            .map(|(_, client)| client)
+            .unwrap_or(&self.client)
+    }
+
+    fn retry_delay(&self, attempt: u32, retry_after: Option<Duration>) -> Duration {
+        retry_after
+            .unwrap_or_else(|| self.backoff.saturating_mul(2u32.saturating_pow(attempt)))
+            .min(MAX_RETRY_DELAY)
+    }
+
+    fn cache_path(&self, url: &str) -> Option<PathBuf> {
+        let name: String = without_query(url)
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect();
+
+        Some(self.cache_dir.as_ref()?.join(format!("{}.json", name)))
+    }
+
+    fn load_cached(&self, url: &str) -> Option<CachedIndex> {
+        let content = fs::read(self.cache_path(url)?).ok()?;
+
+        match serde_json::from_slice(&content) {
+            Ok(cached) => Some(cached),
+            Err(e) => {
+                debug!("ignoring cached index of {}: {}", url, e);
+                None
+            }
+        }
+    }
+
+    fn store_cached(&self, url: &str, cached: &CachedIndex) -> anyhow::Result<()> {
+        let Some(path) = self.cache_path(url) else {
+            return Ok(());
+        };
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        fs::write(path, serde_json::to_vec(cached)?)?;
+
+        Ok(())
+    }
+
+    async fn get(&self, url: Url, headers: HeaderMap) -> reqwest::Result<reqwest::Response> {
+        let mut attempt = 0;
+
+        loop {
+            let res = self
+                .client_for(url.as_str())
+                .get(url.clone())
+                .headers(headers.clone())
+                .send()
+                .await;
+
+            let retry_after = match &res {
+                Ok(res) if is_retryable(res.status()) => parse_retry_after(res.headers()),
+                Err(e) if e.is_timeout() || e.is_connect() => None,
+                _ => return res,
+            };
+
+            if attempt >= self.retries {
+                return res;
+            }
+
+            let delay = self.retry_delay(attempt, retry_after);
+
+            match &res {
+                Ok(res) => {
+                    debug!("{} returned {}, retrying in {:?}", url, res.status(), delay)
+                }
+                Err(e) => debug!("cannot fetch {}: {}, retrying in {:?}", url, e, delay),
+            }
+
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+}
+
+fn is_retryable(status: StatusCode) -> bool {
+    status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS
 }
 
-pub struct HelmRepoReqwestClient {}
+fn parse_retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(header::RETRY_AFTER)?.to_str().ok()?;
+
+    if let Ok(seconds) = value.trim().parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+
+    let date = DateTime::parse_from_rfc2822(value).ok()?;
+
+    Some(
+        (date.with_timezone(&Utc) - Utc::now())
+            .to_std()
+            .unwrap_or_default(),
+    )
+}
 
 #[async_trait]
 impl HelmRepoClient for HelmRepoReqwestClient {
-    async fn get_helm_repo_index(&self, repo_url: &str) -> anyhow::Result<HelmRepoIndex> {
-        let res = reqwest::get(format!("{}/index.yaml", repo_url))
+    async fn get_chart_values(&self, chart_url: &str) -> anyhow::Result<Value> {
+        let res = self
+            .get(Url::parse(chart_url)?, HeaderMap::new())
             .await?
-            .text()
-            .await?;
+            .error_for_status()?;
+
+        chart_values(&res.bytes().await?)
+    }
 
-        let values: Result<HelmRepoIndex, Error> = serde_yaml::from_str(&res);
+    async fn get_helm_repo_index(
+        &self,
+        repo_url: &str,
+        charts: &[String],
+    ) -> anyhow::Result<HelmRepoIndex> {
+        let index_url = self.index_url(repo_url)?;
+        let url = index_url.to_string();
+
+        let unreachable = |status: Option<u16>, reason: String| CheckError::RepoUnreachable {
+            url: without_query(&url),
+            status,
+            reason,
+        };
+
+        // an index cached without one of the charts cannot be revalidated
+        let cached = self
+            .load_cached(&url)
+            .filter(|cached| charts.iter().all(|chart| cached.index.has_chart(chart)));
+
+        let mut headers = HeaderMap::new();
+        if let Some(cached) = &cached {
+            let validators = [
+                (header::IF_NONE_MATCH, &cached.etag),
+                (header::IF_MODIFIED_SINCE, &cached.last_modified),
+            ];
+
+            for (name, value) in validators {
+                if let Some(value) = value.as_deref().and_then(|v| v.parse().ok()) {
+                    headers.insert(name, value);
+                }
+            }
+        }
+
+        let res = self
+            .get(index_url, headers)
+            .await
+            .map_err(|e| unreachable(None, format!("{:#}", anyhow::Error::new(e))))?;
+
+        let status = res.status();
+
+        if status == StatusCode::NOT_MODIFIED {
+            if let Some(cached) = cached {
+                debug!("index of {} is not modified, using cached index", url);
+                return Ok(cached.index);
+            }
+        }
+
+        if matches!(status.as_u16(), 401 | 403) {
+            return Err(CheckError::AuthRequired {
+                url: without_query(&url),
+                status: status.as_u16(),
+            }
+            .into());
+        }
+
+        if !status.is_success() {
+            return Err(unreachable(Some(status.as_u16()), status.to_string()).into());
+        }
+
+        let header_value = |name: header::HeaderName| {
+            res.headers()
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .map(|v| v.to_string())
+        };
+        let etag = header_value(header::ETAG);
+        let last_modified = header_value(header::LAST_MODIFIED);
+
+        let res = res
+            .bytes()
+            .await
+            .map_err(|e| unreachable(None, format!("{:#}", anyhow::Error::new(e))))?;
+
+        let values = HelmRepoIndex::from_yaml(&res, charts);
 
         match values {
-            core::result::Result::Ok(v) => Ok(v),
+            Ok(mut index) => {
+                index.resolve_urls(repo_url);
+
+                if etag.is_none() && last_modified.is_none() {
+                    return Ok(index);
+                }
+
+                let cached = CachedIndex {
+                    etag,
+                    last_modified,
+                    index,
+                };
+                if let Err(e) = self.store_cached(&url, &cached) {
+                    warn!("cannot cache index of {}: {:#}", without_query(&url), e);
+                }
+
+                Ok(cached.index)
+            }
             Err(e) => {
                 debug!("{:?}", e);
 
-                bail!("cannot fetch or deserialize helm repo index")
+                Err(CheckError::IndexParse {
+                    line: e.location().map(|l| l.line()),
+                    column: e.location().map(|l| l.column()),
+                    message: e.to_string(),
+                    url: without_query(&url),
+                }
+                .into())
             }
         }
     }
 }
 
+const FAILED_INDEX_TTL: Duration = Duration::from_secs(60);
+
+type CacheEntry = (Instant, Result<HelmRepoIndex, CheckError>);
+
+pub struct CachedHelmRepoClient<C> {
+    client: C,
+    ttl: Duration,
+    failure_ttl: Duration,
+    cache: Mutex<HashMap<String, CacheEntry>>,
+    fetched: Mutex<HashSet<String>>,
+    charts: Mutex<HashMap<String, HashSet<String>>>,
+}
+
+impl<C: HelmRepoClient> CachedHelmRepoClient<C> {
+    pub fn new(client: C, ttl: Duration) -> Self {
+        Self {
+            client,
+            ttl,
+            failure_ttl: ttl.min(FAILED_INDEX_TTL),
+            cache: Mutex::new(HashMap::new()),
+            fetched: Mutex::new(HashSet::new()),
+            charts: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn with_failure_ttl(mut self, failure_ttl: Duration) -> Self {
+        self.failure_ttl = failure_ttl;
+        self
+    }
+
+    fn is_expired(&self, (fetched_at, index): &CacheEntry) -> bool {
+        let ttl = match index {
+            Ok(_) => self.ttl,
+            Err(_) => self.failure_ttl,
+        };
+
+        fetched_at.elapsed() >= ttl
+    }
+
+    fn cached_index(&self, repo_url: &str) -> Option<Result<HelmRepoIndex, CheckError>> {
+        let cache = self.cache.lock().unwrap();
+
+        cache
+            .get(repo_url)
+            .filter(|entry| !self.is_expired(entry))
+            .map(|(_, index)| index.clone())
+    }
+
+    pub fn expired_repos(&self) -> Vec<String> {
+        let cache = self.cache.lock().unwrap();
+
+        cache
+            .iter()
+            .filter(|(_, entry)| self.is_expired(entry))
+            .map(|(repo_url, _)| repo_url.clone())
+            .collect()
+    }
+
+    pub fn evict_repos(&self, repo_urls: &[String]) {
+        let mut cache = self.cache.lock().unwrap();
+
+        for repo_url in repo_urls {
+            cache.remove(repo_url);
+        }
+    }
+
+    pub fn take_fetched_repos(&self) -> HashSet<String> {
+        std::mem::take(&mut self.fetched.lock().unwrap())
+    }
+
+    // charts expected before the first fetch are parsed from the index at once
+    pub fn expect_chart(&self, repo_url: &str, chart_name: &str) {
+        self.charts
+            .lock()
+            .unwrap()
+            .entry(repo_url.to_string())
+            .or_default()
+            .insert(chart_name.to_string());
+    }
+
+    pub fn expect_source(&self, source: &SourceSpec) {
+        if let (Some(repo_url), Some(chart)) = (&source.repo_url, &source.chart) {
+            self.expect_chart(repo_url, chart);
+        }
+    }
+
+    fn expected_charts(&self, repo_url: &str) -> Vec<String> {
+        let charts = self.charts.lock().unwrap();
+
+        let mut charts: Vec<String> = charts
+            .get(repo_url)
+            .into_iter()
+            .flatten()
+            .cloned()
+            .collect();
+        charts.sort();
+        charts
+    }
+}
+
+#[async_trait]
+impl<C: HelmRepoClient> HelmRepoClient for CachedHelmRepoClient<C> {
+    async fn get_helm_repo_index(
+        &self,
+        repo_url: &str,
+        charts: &[String],
+    ) -> anyhow::Result<HelmRepoIndex> {
+        for chart in charts {
+            self.expect_chart(repo_url, chart);
+        }
+
+        match self.cached_index(repo_url) {
+            Some(Ok(index)) if !charts.iter().all(|chart| index.has_chart(chart)) => {
+                debug!(
+                    "cached index of {} misses a chart, fetching it again",
+                    repo_url
+                );
+            }
+            Some(index) => {
+                debug!("using cached index of {}", repo_url);
+                return Ok(index?);
+            }
+            None => {}
+        }
+
+        let result = self
+            .client
+            .get_helm_repo_index(repo_url, &self.expected_charts(repo_url))
+            .await;
+        self.fetched.lock().unwrap().insert(repo_url.to_string());
+
+        // remember typed failures, so an unreachable repository is not retried for every chart
+        let entry = match &result {
+            Ok(index) => Ok(index.clone()),
+            Err(e) => match e.downcast_ref::<CheckError>() {
+                Some(e) => Err(e.clone()),
+                None => return result,
+            },
+        };
+
+        self.cache
+            .lock()
+            .unwrap()
+            .insert(repo_url.to_string(), (Instant::now(), entry));
+
+        result
+    }
+
+    async fn get_chart_values(&self, chart_url: &str) -> anyhow::Result<Value> {
+        self.client.get_chart_values(chart_url).await
+    }
+}
+
 #[cfg(test)]
 mod test {
 
-    use std::{collections::HashMap, str::FromStr};
+    use std::{
+        cmp::Ordering,
+        collections::{HashMap, HashSet},
+        io::{Read, Write},
+        net::TcpListener,
+        path::PathBuf,
+        str::FromStr,
+        time::Duration,
+    };
 
     use chrono::DateTime;
+    use flate2::{write::GzEncoder, Compression};
+    use openssl::{
+        asn1::Asn1Time,
+        bn::BigNum,
+        hash::MessageDigest,
+        pkey::{PKey, Private},
+        rsa::Rsa,
+        ssl::{SslAcceptor, SslMethod, SslVerifyMode},
+        x509::{
+            extension::{BasicConstraints, ExtendedKeyUsage, SubjectAlternativeName},
+            X509Builder, X509NameBuilder, X509,
+        },
+    };
 
     use crate::{
+        error::CheckError,
         helm::{HelmRepoClient, HelmRepoReqwestClient},
         kubernetes::SourceSpec,
     };
 
-    use super::{HelmChart, HelmRepoChartVersion, HelmRepoIndex, MockHelmRepoClient};
+    use super::{
+        chart_values, compare_versions, join_url, parse_retry_after, removed_values,
+        CachedHelmRepoClient, ChangelogEntry, ClientCert, HelmChart, HelmRepoChartVersion,
+        HelmRepoIndex, HttpOptions, IndexPath, MockHelmRepoClient, RepoOptions,
+    };
 
     fn init_source_spec(
         chart: Option<String>,
@@ -216,21 +1163,68 @@ mod test {
         assert!(result.is_err());
     }
 
+    #[test]
+    fn helm_chart_source_spec_try_from_templated_target_revision() {
+        let source_spec = init_source_spec(
+            Some("chart".to_owned()),
+            Some("repo_url".to_owned()),
+            Some("{{ .values.version }}".to_owned()),
+        );
+
+        let result = HelmChart::try_from(source_spec);
+
+        assert!(matches!(result, Err(CheckError::UnsupportedSource { .. })));
+    }
+
+    #[test]
+    fn helm_chart_source_spec_try_from_templated_chart_and_repo_url() {
+        for (chart, repo_url, reason) in [
+            ("{{ .values.chart }}", "repo_url", "templated chart"),
+            (
+                "chart",
+                "https://{{ .values.registry }}/charts",
+                "templated repo_url",
+            ),
+        ] {
+            let source_spec = init_source_spec(
+                Some(chart.to_owned()),
+                Some(repo_url.to_owned()),
+                Some("1.0.0".to_owned()),
+            );
+
+            let result = HelmChart::try_from(source_spec);
+
+            assert_eq!(
+                Some(reason.to_owned()),
+                match result {
+                    Err(CheckError::UnsupportedSource { reason }) => Some(reason),
+                    _ => None,
+                }
+            );
+        }
+    }
+
     fn create_stub_client() -> MockHelmRepoClient {
         let mut stub_client = MockHelmRepoClient::new();
 
         let versions = vec![
             HelmRepoChartVersion {
-                api_version: Some("api_version".to_owned()),
-                name: "name".to_owned(),
                 version: "v0.1.0".to_owned(),
-                created: DateTime::from_str("2022-11-10T11:40:08.566983693Z").expect("wrong param"),
+                app_version: None,
+                created: Some(
+                    DateTime::from_str("2022-11-10T11:40:08.566983693Z").expect("wrong param"),
+                ),
+                annotations: HashMap::new(),
+                urls: vec!["https://charts.example.com/chart-v0.1.0.tgz?sig=abc".to_owned()],
             },
             HelmRepoChartVersion {
-                api_version: Some("api_version".to_owned()),
-                name: "name".to_owned(),
                 version: "v0.2.0".to_owned(),
-                created: DateTime::from_str("2022-11-11T11:40:08.566983693Z").expect("wrong param"),
+                app_version: None,
+                created: Some(
+                    DateTime::from_str("2022-11-11T11:40:08.566983693Z").expect("wrong param"),
+                ),
+                annotations: HashMap::new(),
+                urls: vec!["https://charts.example.com/chart-v0.2.0.tgz?sig=abc".to_owned()],
             },
         ];
 
@@ -239,10 +1233,11 @@ mod test {
 
         stub_client
             .expect_get_helm_repo_index()
-            .returning(move |_| {
+            .returning(move |_, _| {
                 Ok(HelmRepoIndex {
                     api_version: "v1".to_owned(),
                     entries: entries.clone(),
+                    charts: None,
                 })
             });
 
@@ -277,11 +1272,286 @@ mod test {
             revision: "v0.2.0".to_owned(),
         };
 
-        let result = helm_chart.get_newer_version(&client).await;
-        assert!(result.is_ok());
+        let result = helm_chart.get_newer_version(&client).await;
+        assert!(result.is_ok());
+
+        let value = result.unwrap();
+        assert!(value.is_none());
+    }
+
+    #[test]
+    fn compare_versions_by_semver() {
+        assert_eq!(Ordering::Greater, compare_versions("v0.10.0", "v0.2.0"));
+        assert_eq!(Ordering::Less, compare_versions("1.0.0-rc.1", "1.0.0"));
+        assert_eq!(Ordering::Equal, compare_versions("v1.2.3", "1.2.3"));
+        assert_eq!(Ordering::Less, compare_versions("latest", "stable"));
+    }
+
+    #[tokio::test]
+    async fn helm_chart_get_newer_version_ignores_downgrade() {
+        let client = create_stub_client();
+
+        let helm_chart = HelmChart {
+            chart: "chart".to_owned(),
+            repo: "repo".to_owned(),
+            revision: "v0.10.0".to_owned(),
+        };
+
+        let result = helm_chart.get_newer_version(&client).await;
+
+        assert_eq!(None, result.expect("cannot check chart"));
+    }
+
+    #[tokio::test]
+    async fn helm_chart_get_chart_update_hides_url_query() {
+        let client = create_stub_client();
+
+        let helm_chart = HelmChart {
+            chart: "chart".to_owned(),
+            repo: "repo".to_owned(),
+            revision: "v0.1.0".to_owned(),
+        };
+
+        let result = helm_chart.get_chart_update(&client).await;
+
+        assert_eq!(
+            Some("https://charts.example.com/chart-v0.2.0.tgz".to_owned()),
+            result.expect("cannot check chart").unwrap().url
+        );
+    }
+
+    #[test]
+    fn removed_values_of_nested_keys() {
+        let yaml = |s: &str| serde_yaml::from_str::<serde_yaml::Value>(s).unwrap();
+
+        let mut removed = Vec::new();
+        removed_values(
+            &yaml("loki:\n  auth_enabled: false\n  storage: s3\ncustom: true\nreplicas: 2\n"),
+            &yaml("loki:\n  auth_enabled: true\n  storage: fs\nreplicas: 1\n"),
+            &yaml("loki:\n  storage: fs\n"),
+            "",
+            &mut removed,
+        );
+
+        assert_eq!(vec!["loki.auth_enabled", "replicas"], removed);
+    }
+
+    #[test]
+    fn chart_values_from_archive() {
+        let mut archive = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+
+        for (path, content) in [
+            ("loki/charts/minio/values.yaml", "minio: true\n"),
+            ("loki/values.yaml", "replicas: 1\n"),
+        ] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            archive
+                .append_data(&mut header, path, content.as_bytes())
+                .unwrap();
+        }
+
+        let archive = archive.into_inner().unwrap().finish().unwrap();
+
+        assert_eq!(
+            serde_yaml::from_str::<serde_yaml::Value>("replicas: 1").unwrap(),
+            chart_values(&archive).expect("cannot read archive")
+        );
+    }
+
+    #[tokio::test]
+    async fn helm_chart_get_removed_values() {
+        let mut client = create_stub_client();
+
+        client.expect_get_chart_values().returning(|url| {
+            Ok(serde_yaml::from_str(match url {
+                "https://charts.example.com/chart-v0.1.0.tgz?sig=abc" => {
+                    "replicas: 1\nlegacy: true\n"
+                }
+                _ => "replicas: 1\n",
+            })?)
+        });
+
+        let helm_chart = HelmChart {
+            chart: "chart".to_owned(),
+            repo: "repo".to_owned(),
+            revision: "v0.1.0".to_owned(),
+        };
+
+        let values = serde_yaml::from_str("legacy: false\nreplicas: 3\n").unwrap();
+        let result = helm_chart
+            .get_removed_values(&client, "v0.2.0", &values)
+            .await;
+
+        assert_eq!(vec!["legacy"], result.expect("cannot compare values"));
+    }
+
+    #[tokio::test]
+    async fn cached_helm_repo_client_fetches_index_once() {
+        let mut stub_client = MockHelmRepoClient::new();
+
+        stub_client
+            .expect_get_helm_repo_index()
+            .times(1)
+            .returning(|_, _| {
+                Ok(HelmRepoIndex {
+                    api_version: "v1".to_owned(),
+                    entries: HashMap::new(),
+                    charts: None,
+                })
+            });
+
+        let client = CachedHelmRepoClient::new(stub_client, std::time::Duration::from_secs(60));
+
+        assert!(client
+            .get_helm_repo_index("repo", &["chart".to_owned()])
+            .await
+            .is_ok());
+        assert!(client
+            .get_helm_repo_index("repo", &["chart".to_owned()])
+            .await
+            .is_ok());
+        assert!(client.expired_repos().is_empty());
+
+        let client = CachedHelmRepoClient::new(MockHelmRepoClient::new(), Default::default());
+        client.cache.lock().unwrap().insert(
+            "repo".to_owned(),
+            (
+                std::time::Instant::now(),
+                Ok(HelmRepoIndex {
+                    api_version: "v1".to_owned(),
+                    entries: HashMap::new(),
+                    charts: None,
+                }),
+            ),
+        );
+
+        assert_eq!(vec!["repo".to_owned()], client.expired_repos());
+    }
+
+    #[tokio::test]
+    async fn cached_helm_repo_client_fetches_expected_charts_at_once() {
+        let mut stub_client = MockHelmRepoClient::new();
+
+        let index = |charts: &[String]| {
+            Ok(HelmRepoIndex {
+                api_version: "v1".to_owned(),
+                entries: HashMap::new(),
+                charts: Some(charts.iter().cloned().collect()),
+            })
+        };
+
+        stub_client
+            .expect_get_helm_repo_index()
+            .withf(|_, charts| charts == ["cert-manager".to_owned(), "trust-manager".to_owned()])
+            .times(1)
+            .returning(move |_, charts| index(charts));
+        stub_client
+            .expect_get_helm_repo_index()
+            .withf(|_, charts| charts.len() == 3)
+            .times(1)
+            .returning(move |_, charts| index(charts));
+
+        let client = CachedHelmRepoClient::new(stub_client, Duration::from_secs(60));
+        client.expect_chart("repo", "trust-manager");
+
+        for chart in ["cert-manager", "trust-manager", "cert-manager"] {
+            let result = client
+                .get_helm_repo_index("repo", &[chart.to_owned()])
+                .await;
+
+            assert!(result.expect("cannot fetch index").has_chart(chart));
+        }
+
+        // a chart that was not expected needs the index again
+        let result = client
+            .get_helm_repo_index("repo", &["istio".to_owned()])
+            .await;
+        assert!(result.expect("cannot fetch index").has_chart("istio"));
+    }
+
+    #[tokio::test]
+    async fn cached_helm_repo_client_remembers_failures() {
+        let mut stub_client = MockHelmRepoClient::new();
+
+        stub_client
+            .expect_get_helm_repo_index()
+            .times(1)
+            .returning(|url, _| {
+                Err(CheckError::RepoUnreachable {
+                    url: url.to_owned(),
+                    status: Some(503),
+                    reason: "Service Unavailable".to_owned(),
+                }
+                .into())
+            });
+
+        let client = CachedHelmRepoClient::new(stub_client, Duration::from_secs(3600));
+
+        for _ in 0..2 {
+            let result = client
+                .get_helm_repo_index("repo", &["chart".to_owned()])
+                .await;
+
+            assert!(matches!(
+                result.unwrap_err().downcast_ref::<CheckError>(),
+                Some(CheckError::RepoUnreachable {
+                    status: Some(503),
+                    ..
+                })
+            ));
+        }
+
+        assert!(client.expired_repos().is_empty());
+        assert_eq!(Duration::from_secs(60), client.failure_ttl);
+        assert_eq!(
+            HashSet::from(["repo".to_owned()]),
+            client.take_fetched_repos()
+        );
+        assert!(client.take_fetched_repos().is_empty());
+
+        client.evict_repos(&["repo".to_owned()]);
+        assert!(client.cached_index("repo").is_none());
+    }
+
+    #[tokio::test]
+    async fn cached_helm_repo_client_remembers_failures_for_the_scan() {
+        let mut stub_client = MockHelmRepoClient::new();
+
+        stub_client
+            .expect_get_helm_repo_index()
+            .times(1)
+            .returning(|url, _| {
+                Err(CheckError::ChartNotFound {
+                    chart: url.to_owned(),
+                }
+                .into())
+            });
 
-        let value = result.unwrap();
-        assert!(value.is_none());
+        let client =
+            CachedHelmRepoClient::new(stub_client, Duration::MAX).with_failure_ttl(Duration::MAX);
+
+        client.cache.lock().unwrap().insert(
+            "stale".to_owned(),
+            (
+                std::time::Instant::now() - Duration::from_secs(3600),
+                Err(CheckError::ChartNotFound {
+                    chart: "loki".to_owned(),
+                }),
+            ),
+        );
+
+        assert!(client
+            .get_helm_repo_index("repo", &["chart".to_owned()])
+            .await
+            .is_err());
+        assert!(client
+            .get_helm_repo_index("repo", &["chart".to_owned()])
+            .await
+            .is_err());
+        assert!(client.expired_repos().is_empty());
     }
 
     #[test]
@@ -293,27 +1563,40 @@ mod test {
         let index = HelmRepoIndex {
             api_version: "v1".to_owned(),
             entries,
+            charts: None,
         };
 
         let result = index.get_newest_chart_version("invalid_chart");
 
         assert!(result.is_err());
+        assert_eq!(
+            CheckError::ChartNotFound {
+                chart: "invalid_chart".to_owned()
+            },
+            result.unwrap_err()
+        );
     }
 
     #[test]
     fn helm_repo_index_get_newest_chart_version_valid_chart_name() {
         let versions = vec![
             HelmRepoChartVersion {
-                api_version: Some("api_version".to_owned()),
-                name: "name".to_owned(),
                 version: "v0.1.0".to_owned(),
-                created: DateTime::from_str("2022-11-10T11:40:08.566983693Z").expect("wrong param"),
+                app_version: None,
+                created: Some(
+                    DateTime::from_str("2022-11-10T11:40:08.566983693Z").expect("wrong param"),
+                ),
+                annotations: HashMap::new(),
+                urls: Vec::new(),
             },
             HelmRepoChartVersion {
-                api_version: Some("api_version".to_owned()),
-                name: "name".to_owned(),
                 version: "v0.2.0".to_owned(),
-                created: DateTime::from_str("2022-11-11T11:40:08.566983693Z").expect("wrong param"),
+                app_version: None,
+                created: Some(
+                    DateTime::from_str("2022-11-11T11:40:08.566983693Z").expect("wrong param"),
+                ),
+                annotations: HashMap::new(),
+                urls: Vec::new(),
             },
         ];
 
@@ -323,6 +1606,7 @@ mod test {
         let index = HelmRepoIndex {
             api_version: "v1".to_owned(),
             entries,
+            charts: None,
         };
 
         let result = index.get_newest_chart_version("chart");
@@ -335,16 +1619,22 @@ mod test {
     fn helm_repo_index_get_newest_chart_version_valid_chart_name_with_sort() {
         let verions = vec![
             HelmRepoChartVersion {
-                api_version: Some("api_version".to_owned()),
-                name: "name".to_owned(),
                 version: "v0.2.0".to_owned(),
-                created: DateTime::from_str("2022-11-11T11:40:08.566983693Z").expect("wrong param"),
+                app_version: None,
+                created: Some(
+                    DateTime::from_str("2022-11-11T11:40:08.566983693Z").expect("wrong param"),
+                ),
+                annotations: HashMap::new(),
+                urls: Vec::new(),
             },
             HelmRepoChartVersion {
-                api_version: Some("api_version".to_owned()),
-                name: "name".to_owned(),
                 version: "v0.1.0".to_owned(),
-                created: DateTime::from_str("2022-11-10T11:40:08.566983693Z").expect("wrong param"),
+                app_version: None,
+                created: Some(
+                    DateTime::from_str("2022-11-10T11:40:08.566983693Z").expect("wrong param"),
+                ),
+                annotations: HashMap::new(),
+                urls: Vec::new(),
             },
         ];
 
@@ -354,6 +1644,7 @@ mod test {
         let index = HelmRepoIndex {
             api_version: "v1".to_owned(),
             entries,
+            charts: None,
         };
 
         let result = index.get_newest_chart_version("chart");
@@ -362,18 +1653,659 @@ mod test {
         assert_eq!("v0.2.0", result.unwrap());
     }
 
+    #[test]
+    fn helm_repo_index_get_changelog_between_versions() {
+        let version = |version: &str, changes: Option<&str>| HelmRepoChartVersion {
+            version: version.to_owned(),
+            app_version: None,
+            created: Some(
+                DateTime::from_str("2022-11-10T11:40:08.566983693Z").expect("wrong param"),
+            ),
+            annotations: changes
+                .map(|c| HashMap::from([("artifacthub.io/changes".to_owned(), c.to_owned())]))
+                .unwrap_or_default(),
+            urls: Vec::new(),
+        };
+
+        let mut entries = HashMap::new();
+        entries.insert(
+            "chart".to_owned(),
+            vec![
+                version("v0.1.0", Some("- initial release")),
+                version("v0.2.0", Some("- kind: added\n  description: new feature")),
+                version("v0.3.0", None),
+                version("v0.4.0", Some("- fixed bug")),
+                version("v0.5.0", Some("- not yet")),
+            ],
+        );
+
+        let index = HelmRepoIndex {
+            api_version: "v1".to_owned(),
+            entries,
+            charts: None,
+        };
+
+        let result = index.get_changelog("chart", "v0.1.0", "v0.4.0");
+
+        let expected = vec![
+            ChangelogEntry {
+                version: "v0.4.0".to_owned(),
+                kind: None,
+                description: "fixed bug".to_owned(),
+            },
+            ChangelogEntry {
+                version: "v0.2.0".to_owned(),
+                kind: Some("added".to_owned()),
+                description: "new feature".to_owned(),
+            },
+        ];
+
+        assert_eq!(expected, result);
+    }
+
+    fn init_http_options() -> HttpOptions {
+        HttpOptions {
+            connect_timeout: Duration::from_secs(1),
+            read_timeout: Duration::from_secs(1),
+            retries: 2,
+            ca_certs: Vec::new(),
+            client_cert: None,
+            proxy: None,
+            no_proxy: None,
+            repos: Vec::new(),
+            index_paths: Vec::new(),
+            cache_dir: None,
+        }
+    }
+
+    fn init_reqwest_client_with(options: HttpOptions) -> HelmRepoReqwestClient {
+        let mut client = HelmRepoReqwestClient::new(&options).expect("cannot build client");
+
+        client.backoff = Duration::from_millis(1);
+        client
+    }
+
+    fn init_reqwest_client() -> HelmRepoReqwestClient {
+        init_reqwest_client_with(init_http_options())
+    }
+
+    #[test]
+    fn helm_repo_client_cache_path_without_query() {
+        let mut options = init_http_options();
+        options.cache_dir = Some(PathBuf::from("cache"));
+
+        let client = init_reqwest_client_with(options);
+
+        assert_eq!(
+            Some(PathBuf::from(
+                "cache/https___charts_example_com_index_yaml.json"
+            )),
+            client.cache_path("https://charts.example.com/index.yaml?token=secret")
+        );
+    }
+
+    #[test]
+    fn helm_repo_client_skips_invalid_repo_certificate() {
+        let options = HttpOptions {
+            repos: vec![
+                RepoOptions {
+                    url: "https://broken.example.com".to_owned(),
+                    insecure: false,
+                    client_cert: Some(ClientCert {
+                        cert: "not a certificate".to_owned(),
+                        key: "not a key".to_owned(),
+                    }),
+                },
+                RepoOptions {
+                    url: "https://insecure.example.com/".to_owned(),
+                    insecure: true,
+                    client_cert: None,
+                },
+            ],
+            ..init_http_options()
+        };
+
+        let client = HelmRepoReqwestClient::new(&options).expect("cannot build client");
+
+        assert_eq!(
+            vec!["https://insecure.example.com"],
+            client
+                .repo_clients
+                .iter()
+                .map(|(url, _)| url.as_str())
+                .collect::<Vec<&str>>()
+        );
+    }
+
+    fn init_certificate(
+        name: &str,
+        issuer: Option<&(X509, PKey<Private>)>,
+    ) -> (X509, PKey<Private>) {
+        let key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+
+        let mut subject = X509NameBuilder::new().unwrap();
+        subject.append_entry_by_text("CN", name).unwrap();
+        let subject = subject.build();
+
+        let mut builder = X509Builder::new().unwrap();
+        builder.set_version(2).unwrap();
+        builder
+            .set_serial_number(
+                &BigNum::from_u32(rand_serial())
+                    .unwrap()
+                    .to_asn1_integer()
+                    .unwrap(),
+            )
+            .unwrap();
+        builder.set_subject_name(&subject).unwrap();
+        builder.set_pubkey(&key).unwrap();
+        builder
+            .set_not_before(&Asn1Time::days_from_now(0).unwrap())
+            .unwrap();
+        builder
+            .set_not_after(&Asn1Time::days_from_now(1).unwrap())
+            .unwrap();
+
+        match issuer {
+            None => {
+                builder.set_issuer_name(&subject).unwrap();
+                builder
+                    .append_extension(BasicConstraints::new().critical().ca().build().unwrap())
+                    .unwrap();
+                builder.sign(&key, MessageDigest::sha256()).unwrap();
+            }
+            Some((ca, ca_key)) => {
+                builder.set_issuer_name(ca.subject_name()).unwrap();
+                let san = SubjectAlternativeName::new()
+                    .dns(name)
+                    .build(&builder.x509v3_context(Some(ca), None))
+                    .unwrap();
+                builder.append_extension(san).unwrap();
+                builder
+                    .append_extension(
+                        ExtendedKeyUsage::new()
+                            .server_auth()
+                            .client_auth()
+                            .build()
+                            .unwrap(),
+                    )
+                    .unwrap();
+                builder.sign(ca_key, MessageDigest::sha256()).unwrap();
+            }
+        }
+
+        (builder.build(), key)
+    }
+
+    fn rand_serial() -> u32 {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .subsec_nanos()
+    }
+
+    fn serve_tls_index(server: &(X509, PKey<Private>), client_ca: Option<&X509>) -> String {
+        let mut acceptor = SslAcceptor::mozilla_intermediate_v5(SslMethod::tls()).unwrap();
+        acceptor.set_certificate(&server.0).unwrap();
+        acceptor.set_private_key(&server.1).unwrap();
+
+        if let Some(client_ca) = client_ca {
+            acceptor
+                .cert_store_mut()
+                .add_cert(client_ca.clone())
+                .unwrap();
+            acceptor.set_verify(SslVerifyMode::PEER | SslVerifyMode::FAIL_IF_NO_PEER_CERT);
+        }
+
+        let acceptor = acceptor.build();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        std::thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let Ok(mut stream) = acceptor.accept(stream) else {
+                    continue;
+                };
+
+                let body = "apiVersion: v1\nentries: {}\n";
+                let _ = stream.read(&mut [0; 4096]);
+                let _ = write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                );
+                let _ = stream.shutdown();
+            }
+        });
+
+        format!("https://localhost:{}", port)
+    }
+
+    #[tokio::test]
+    async fn helm_repo_client_trusts_additional_ca() {
+        let ca = init_certificate("argo-helm-updater-ca", None);
+        let url = serve_tls_index(&init_certificate("localhost", Some(&ca)), None);
+
+        let result = init_reqwest_client()
+            .get_helm_repo_index(&url, &["chart".to_owned()])
+            .await;
+        assert!(matches!(
+            result.unwrap_err().downcast_ref::<CheckError>(),
+            Some(CheckError::RepoUnreachable { status: None, .. })
+        ));
+
+        let mut options = init_http_options();
+        options.ca_certs = vec![String::from_utf8(ca.0.to_pem().unwrap()).unwrap()];
+
+        let result = init_reqwest_client_with(options)
+            .get_helm_repo_index(&url, &["chart".to_owned()])
+            .await;
+        assert!(result.is_ok());
+    }
+
+    #[tokio::test]
+    async fn helm_repo_client_skips_verification_for_insecure_repo() {
+        let insecure_url = serve_tls_index(&init_certificate("localhost", None), None);
+        let url = serve_tls_index(&init_certificate("localhost", None), None);
+
+        let mut options = init_http_options();
+        options.repos = vec![RepoOptions {
+            url: format!("{}/", insecure_url),
+            insecure: true,
+            client_cert: None,
+        }];
+
+        let helm_repo_client = init_reqwest_client_with(options);
+
+        assert!(helm_repo_client
+            .get_helm_repo_index(&insecure_url, &["chart".to_owned()])
+            .await
+            .is_ok());
+
+        let result = helm_repo_client
+            .get_helm_repo_index(&url, &["chart".to_owned()])
+            .await;
+        assert!(matches!(
+            result.unwrap_err().downcast_ref::<CheckError>(),
+            Some(CheckError::RepoUnreachable { status: None, reason, .. }) if reason.contains("certificate")
+        ));
+    }
+
+    #[tokio::test]
+    async fn helm_repo_client_presents_client_certificate() {
+        let ca = init_certificate("argo-helm-updater-ca", None);
+        let client = init_certificate("argo-helm-updater", Some(&ca));
+        let url = serve_tls_index(&init_certificate("localhost", Some(&ca)), Some(&ca.0));
+
+        let mut options = init_http_options();
+        options.ca_certs = vec![String::from_utf8(ca.0.to_pem().unwrap()).unwrap()];
+
+        let result = init_reqwest_client_with(options.clone())
+            .get_helm_repo_index(&url, &["chart".to_owned()])
+            .await;
+        assert!(result.is_err());
+
+        options.repos = vec![RepoOptions {
+            url: url.clone(),
+            insecure: false,
+            client_cert: Some(ClientCert {
+                cert: String::from_utf8(client.0.to_pem().unwrap()).unwrap(),
+                key: String::from_utf8(client.1.rsa().unwrap().private_key_to_pem().unwrap())
+                    .unwrap(),
+            }),
+        }];
+
+        let result = init_reqwest_client_with(options)
+            .get_helm_repo_index(&url, &["chart".to_owned()])
+            .await;
+        assert!(result.is_ok());
+    }
+
+    #[tokio::test]
+    async fn helm_repo_client_uses_proxy() {
+        let mut server = mockito::Server::new_async().await;
+
+        let mock = server
+            .mock("GET", "/index.yaml")
+            .match_header("host", "charts.example.invalid")
+            .with_status(200)
+            .with_body("apiVersion: v1\nentries: {}")
+            .expect(1)
+            .create();
+
+        let mut options = init_http_options();
+        options.proxy = Some(server.url());
+        options.no_proxy = Some("127.0.0.1".to_owned());
+
+        let helm_repo_client = init_reqwest_client_with(options);
+
+        let result = helm_repo_client
+            .get_helm_repo_index("http://charts.example.invalid", &["chart".to_owned()])
+            .await;
+        assert!(result.is_ok());
+
+        let result = helm_repo_client
+            .get_helm_repo_index("http://127.0.0.1:1", &["chart".to_owned()])
+            .await;
+        assert!(result.is_err());
+
+        mock.assert();
+    }
+
     #[tokio::test]
     async fn helm_repo_client_get_helm_repo_index_error_on_404() {
         let mut server = mockito::Server::new_async().await;
 
         let mock = server.mock("GET", "/index.yaml").with_status(404).create();
 
-        let helm_repo_client = HelmRepoReqwestClient {};
+        let helm_repo_client = init_reqwest_client();
 
-        let result = helm_repo_client.get_helm_repo_index(&server.url()).await;
+        let result = helm_repo_client
+            .get_helm_repo_index(&server.url(), &["chart".to_owned()])
+            .await;
 
         mock.assert();
         assert!(result.is_err());
+        assert!(matches!(
+            result.unwrap_err().downcast_ref::<CheckError>(),
+            Some(CheckError::RepoUnreachable {
+                status: Some(404),
+                ..
+            })
+        ));
+    }
+
+    #[tokio::test]
+    async fn helm_repo_client_get_helm_repo_index_error_on_401() {
+        let mut server = mockito::Server::new_async().await;
+
+        let mock = server.mock("GET", "/index.yaml").with_status(401).create();
+
+        let helm_repo_client = init_reqwest_client();
+
+        let result = helm_repo_client
+            .get_helm_repo_index(&server.url(), &["chart".to_owned()])
+            .await;
+
+        mock.assert();
+        assert!(matches!(
+            result.unwrap_err().downcast_ref::<CheckError>(),
+            Some(CheckError::AuthRequired { status: 401, .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn helm_repo_client_get_helm_repo_index_retries_server_errors() {
+        let mut server = mockito::Server::new_async().await;
+
+        let unavailable = server
+            .mock("GET", "/index.yaml")
+            .with_status(503)
+            .expect(1)
+            .create();
+        let throttled = server
+            .mock("GET", "/index.yaml")
+            .with_status(429)
+            .with_header("retry-after", "0")
+            .expect(1)
+            .create();
+        let success = server
+            .mock("GET", "/index.yaml")
+            .with_status(200)
+            .with_body("apiVersion: v1\nentries: {}")
+            .create();
+
+        let helm_repo_client = init_reqwest_client();
+
+        let result = helm_repo_client
+            .get_helm_repo_index(&server.url(), &["chart".to_owned()])
+            .await;
+
+        unavailable.assert();
+        throttled.assert();
+        success.assert();
+        assert!(result.is_ok());
+    }
+
+    #[tokio::test]
+    async fn helm_repo_client_get_helm_repo_index_gives_up_after_retries() {
+        let mut server = mockito::Server::new_async().await;
+
+        let mock = server
+            .mock("GET", "/index.yaml")
+            .with_status(500)
+            .expect(3)
+            .create();
+
+        let helm_repo_client = init_reqwest_client();
+
+        let result = helm_repo_client
+            .get_helm_repo_index(&server.url(), &["chart".to_owned()])
+            .await;
+
+        mock.assert();
+        assert!(matches!(
+            result.unwrap_err().downcast_ref::<CheckError>(),
+            Some(CheckError::RepoUnreachable {
+                status: Some(500),
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn join_url_with_paths_and_queries() {
+        let join = |base: &str, path: &str| join_url(base, path).unwrap().to_string();
+
+        assert_eq!(
+            "https://charts.example.com/index.yaml",
+            join("https://charts.example.com", "index.yaml")
+        );
+        assert_eq!(
+            "https://charts.example.com/stable/index.yaml",
+            join("https://charts.example.com/stable/", "index.yaml")
+        );
+        assert_eq!(
+            "https://charts.example.com/stable/index.yaml?sig=a%3D&exp=1",
+            join(
+                "https://charts.example.com/stable?sig=a%3D&exp=1",
+                "index.yaml"
+            )
+        );
+        assert_eq!(
+            "https://cdn.example.com/loki-5.1.0.tgz",
+            join(
+                "https://charts.example.com/stable?sig=a",
+                "https://cdn.example.com/loki-5.1.0.tgz"
+            )
+        );
+        assert!(join_url("charts.example.com", "index.yaml").is_err());
+    }
+
+    #[test]
+    fn index_path_from_str() {
+        let index_path: IndexPath = "https://charts.example.com/?token=a=b=charts/index.yaml"
+            .parse()
+            .expect("invalid index path");
+
+        assert_eq!("https://charts.example.com/?token=a=b", index_path.repo_url);
+        assert_eq!("charts/index.yaml", index_path.path);
+        assert!("https://charts.example.com".parse::<IndexPath>().is_err());
+    }
+
+    #[tokio::test]
+    async fn helm_repo_client_get_helm_repo_index_with_index_path_and_relative_urls() {
+        let mut server = mockito::Server::new_async().await;
+
+        let mock = server
+            .mock("GET", "/repo/charts.yaml")
+            .match_query(mockito::Matcher::UrlEncoded(
+                "token".to_owned(),
+                "abc".to_owned(),
+            ))
+            .with_status(200)
+            .with_body(
+                "apiVersion: v1
+entries:
+  name:
+  - created: \"2023-06-20T18:03:27.348311421Z\"
+    name: name
+    version: v0.2.0
+    urls:
+    - charts/name-v0.2.0.tgz
+    - https://cdn.example.com/name-v0.2.0.tgz",
+            )
+            .create();
+
+        let repo_url = format!("{}/repo/?token=abc", server.url());
+
+        let mut options = init_http_options();
+        options.index_paths = vec![IndexPath {
+            repo_url: repo_url.clone(),
+            path: "charts.yaml".to_owned(),
+        }];
+
+        let result = init_reqwest_client_with(options)
+            .get_helm_repo_index(&repo_url, &["name".to_owned()])
+            .await;
+
+        mock.assert();
+        assert_eq!(
+            vec![
+                format!("{}/repo/charts/name-v0.2.0.tgz?token=abc", server.url()),
+                "https://cdn.example.com/name-v0.2.0.tgz".to_owned(),
+            ],
+            result.expect("cannot fetch index").entries["name"][0].urls
+        );
+    }
+
+    #[test]
+    fn helm_repo_index_keeps_only_changes_annotation() {
+        let index: HelmRepoIndex = serde_yaml::from_str(
+            "apiVersion: v1
+entries:
+  name:
+  - created: \"2023-06-20T18:03:27.348311421Z\"
+    name: name
+    version: v0.2.0
+    annotations:
+      artifacthub.io/changes: '- initial release'
+      images: |
+        - name: name
+          image: registry.example.com/name:v0.2.0
+      category:
+        nested: value
+  - created: \"2023-06-20T18:03:27.348311421Z\"
+    name: name
+    version: v0.1.0
+    annotations:",
+        )
+        .expect("cannot parse index");
+
+        let versions = &index.entries["name"];
+        assert_eq!(
+            HashMap::from([(
+                "artifacthub.io/changes".to_owned(),
+                "- initial release".to_owned()
+            )]),
+            versions[0].annotations
+        );
+        assert!(versions[1].annotations.is_empty());
+    }
+
+    #[tokio::test]
+    async fn helm_repo_client_get_helm_repo_index_gzip() {
+        let mut server = mockito::Server::new_async().await;
+
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder
+            .write_all(b"apiVersion: v1\nentries: {}")
+            .expect("cannot compress index");
+
+        let mock = server
+            .mock("GET", "/index.yaml")
+            .match_header(
+                "accept-encoding",
+                mockito::Matcher::Regex("gzip".to_owned()),
+            )
+            .with_status(200)
+            .with_header("content-encoding", "gzip")
+            .with_body(encoder.finish().expect("cannot compress index"))
+            .create();
+
+        let result = init_reqwest_client()
+            .get_helm_repo_index(&server.url(), &["chart".to_owned()])
+            .await;
+
+        mock.assert();
+        assert!(result.is_ok());
+    }
+
+    #[tokio::test]
+    async fn helm_repo_client_get_helm_repo_index_revalidates_disk_cache() {
+        let mut server = mockito::Server::new_async().await;
+        let cache_dir = tempfile::tempdir().expect("cannot create temp dir");
+
+        let fetched = server
+            .mock("GET", "/index.yaml")
+            .with_status(200)
+            .with_header("etag", "\"abc\"")
+            .with_body(
+                "apiVersion: v1
+entries:
+  name:
+  - created: \"2023-06-20T18:03:27.348311421Z\"
+    name: name
+    version: v0.2.0",
+            )
+            .expect(1)
+            .create();
+        let not_modified = server
+            .mock("GET", "/index.yaml")
+            .match_header("if-none-match", "\"abc\"")
+            .with_status(304)
+            .expect(1)
+            .create();
+
+        let mut options = init_http_options();
+        options.cache_dir = Some(cache_dir.path().to_path_buf());
+
+        let first = init_reqwest_client_with(options.clone())
+            .get_helm_repo_index(&server.url(), &["name".to_owned()])
+            .await
+            .expect("cannot fetch index");
+        let second = init_reqwest_client_with(options)
+            .get_helm_repo_index(&server.url(), &["name".to_owned()])
+            .await
+            .expect("cannot fetch index");
+
+        fetched.assert();
+        not_modified.assert();
+        assert_eq!(first, second);
+        assert_eq!(
+            Ok("v0.2.0".to_owned()),
+            second.get_newest_chart_version("name")
+        );
+    }
+
+    #[test]
+    fn retry_after_seconds_and_date() {
+        let mut headers = reqwest::header::HeaderMap::new();
+        assert_eq!(None, parse_retry_after(&headers));
+
+        headers.insert(reqwest::header::RETRY_AFTER, "120".parse().unwrap());
+        assert_eq!(Some(Duration::from_secs(120)), parse_retry_after(&headers));
+
+        headers.insert(
+            reqwest::header::RETRY_AFTER,
+            "Wed, 21 Oct 2015 07:28:00 GMT".parse().unwrap(),
+        );
+        assert_eq!(Some(Duration::ZERO), parse_retry_after(&headers));
+
+        let date = (chrono::Utc::now() + chrono::Duration::seconds(30)).to_rfc2822();
+        headers.insert(reqwest::header::RETRY_AFTER, date.parse().unwrap());
+        assert!(parse_retry_after(&headers).is_some_and(|d| d <= Duration::from_secs(30)));
     }
 
     #[tokio::test]
@@ -387,12 +2319,46 @@ mod test {
             .with_body("I'm an invalid body.")
             .create();
 
-        let helm_repo_client = HelmRepoReqwestClient {};
+        let helm_repo_client = init_reqwest_client();
 
-        let result = helm_repo_client.get_helm_repo_index(&server.url()).await;
+        let result = helm_repo_client
+            .get_helm_repo_index(&server.url(), &["chart".to_owned()])
+            .await;
 
         mock.assert();
         assert!(result.is_err());
+        assert!(matches!(
+            result.unwrap_err().downcast_ref::<CheckError>(),
+            Some(CheckError::IndexParse { line: Some(1), .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn helm_repo_client_get_helm_repo_index_parse_error_hides_token() {
+        let mut server = mockito::Server::new_async().await;
+
+        let mock = server
+            .mock("GET", "/index.yaml")
+            .match_query(mockito::Matcher::Any)
+            .with_status(200)
+            .with_body("I'm an invalid body.")
+            .create();
+
+        let result = init_reqwest_client()
+            .get_helm_repo_index(
+                &format!("{}/?token=secret", server.url()),
+                &["chart".to_owned()],
+            )
+            .await;
+
+        mock.assert();
+
+        let error = result.unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<CheckError>(),
+            Some(CheckError::IndexParse { url, .. }) if url == &format!("{}/index.yaml", server.url())
+        ));
+        assert!(!format!("{:#}", error).contains("secret"));
     }
 
     #[tokio::test]
@@ -410,25 +2376,47 @@ entries:
   - apiVersion: v1
     created: \"2023-06-20T18:03:27.348311421Z\"
     name: name
-    version: v0.2.0",
+    version: v0.2.0
+  - apiVersion: v1
+    name: name
+    version: v0.1.0
+  trust-manager:
+  - apiVersion: v1
+    created: \"2023-06-20T18:03:27.348311421Z\"
+    name: trust-manager
+    version: v0.1.0",
             )
             .create();
 
-        let helm_repo_client = HelmRepoReqwestClient {};
+        let helm_repo_client = init_reqwest_client();
 
-        let result = helm_repo_client.get_helm_repo_index(&server.url()).await;
+        let result = helm_repo_client
+            .get_helm_repo_index(&server.url(), &["cert-manager".to_owned()])
+            .await;
 
         mock.assert();
         assert!(result.is_ok());
 
         let value = result.unwrap();
 
-        let versions = vec![HelmRepoChartVersion {
-            api_version: Some("v1".to_owned()),
-            name: "name".to_owned(),
-            version: "v0.2.0".to_owned(),
-            created: DateTime::from_str("2023-06-20T18:03:27.348311421Z").expect("wrong param"),
-        }];
+        let versions = vec![
+            HelmRepoChartVersion {
+                version: "v0.2.0".to_owned(),
+                app_version: None,
+                created: Some(
+                    DateTime::from_str("2023-06-20T18:03:27.348311421Z").expect("wrong param"),
+                ),
+                annotations: HashMap::new(),
+                urls: Vec::new(),
+            },
+            HelmRepoChartVersion {
+                version: "v0.1.0".to_owned(),
+                app_version: None,
+                created: None,
+                annotations: HashMap::new(),
+                urls: Vec::new(),
+            },
+        ];
 
         let mut entries = HashMap::new();
         entries.insert("cert-manager".to_owned(), versions);
@@ -436,6 +2424,7 @@ entries:
         let expected_value = HelmRepoIndex {
             api_version: "v1".to_owned(),
             entries,
+            charts: Some(HashSet::from(["cert-manager".to_owned()])),
         };
         assert_eq!(expected_value, value);
     }
@@ -1,19 +1,33 @@
-use anyhow::Ok;
+use std::{fmt::Display, time::Duration};
+
+use anyhow::{bail, Ok};
+use chrono::{DateTime, Utc};
 use hyper_util::rt::TokioExecutor;
+use k8s_openapi::api::core::v1::Secret;
 use kube::{
     api::{ListParams, Patch, PatchParams},
     client::ConfigExt,
     config::KubeConfigOptions,
-    Api, Client, Config, ResourceExt,
+    runtime::events::{Event, EventType, Recorder, Reporter},
+    Api, Client, Config, Resource, ResourceExt,
 };
 use kube_derive::CustomResource;
-use log::debug;
+use log::{debug, info};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
 use tower::BoxError;
 
-use crate::helm::HelmChart;
+use crate::{
+    error::CheckError,
+    helm::{ClientCert, HelmChart, RepoOptions},
+};
+
+const TRACKING_ID_ANNOTATION: &str = "argocd.argoproj.io/tracking-id";
+pub const LATEST_AVAILABLE_ANNOTATION: &str = "argo-helm-updater/latest-available";
+const OUTDATED_REASON: &str = "HelmChartOutdated";
+const REPORT_NAME: &str = "argo-helm-updater";
+const SECRET_TYPE_LABEL: &str = "argocd.argoproj.io/secret-type";
 
 pub async fn init_client(
     context: Option<String>,
@@ -45,6 +59,7 @@ pub async fn init_client(
     group = "argoproj.io",
     version = "v1alpha1",
     kind = "Application",
+    status = "ApplicationStatus",
     namespaced
 )]
 pub struct ApplicationSpec {
@@ -53,16 +68,16 @@ pub struct ApplicationSpec {
     pub sources: Option<Vec<SourceSpec>>,
 }
 
-impl Application {
+impl ApplicationSpec {
     pub fn helm_in_source(&self) -> bool {
-        match &self.spec.source {
+        match &self.source {
             Some(source) => source.is_helm(),
             None => false,
         }
     }
 
     pub fn helm_in_sources(&self) -> bool {
-        match &self.spec.sources {
+        match &self.sources {
             Some(sources) => sources.iter().filter(|s| s.is_helm()).count() > 0,
             None => false,
         }
@@ -79,6 +94,380 @@ impl Application {
 
         false
     }
+
+    pub fn helm_sources(&self) -> Vec<(Option<usize>, SourceSpec)> {
+        let mut output = Vec::new();
+
+        if self.helm_in_source() {
+            output.push((None, self.source.clone().unwrap()));
+        }
+
+        if self.helm_in_sources() {
+            for (index, source) in self.sources.clone().unwrap().into_iter().enumerate() {
+                output.push((Some(index), source));
+            }
+        }
+
+        output
+    }
+}
+
+#[derive(CustomResource, Deserialize, Serialize, Clone, Debug, JsonSchema)]
+#[kube(
+    group = "argoproj.io",
+    version = "v1alpha1",
+    kind = "ApplicationSet",
+    namespaced
+)]
+pub struct ApplicationSetSpec {
+    pub template: ApplicationSetTemplate,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, JsonSchema)]
+pub struct ApplicationSetTemplate {
+    pub spec: ApplicationSpec,
+}
+
+#[derive(CustomResource, Deserialize, Serialize, Clone, Debug, PartialEq, JsonSchema)]
+#[kube(
+    group = "argo-helm-updater.io",
+    version = "v1alpha1",
+    kind = "ChartUpdateReport",
+    namespaced
+)]
+pub struct ChartUpdateReportSpec {
+    #[serde(rename = "generatedAt")]
+    pub generated_at: DateTime<Utc>,
+    pub findings: Vec<ReportedFinding>,
+    pub errors: Vec<ReportedError>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, JsonSchema)]
+pub struct ReportedFinding {
+    pub kind: String,
+    #[serde(rename = "resourceKind")]
+    pub resource_kind: String,
+    pub application: String,
+    pub chart: String,
+    pub repo: String,
+    pub current: String,
+    pub latest: Option<String>,
+    #[serde(rename = "updateKind")]
+    pub update_kind: Option<String>,
+    pub deployed: Option<String>,
+    #[serde(rename = "firstSeen")]
+    pub first_seen: DateTime<Utc>,
+}
+
+impl ReportedFinding {
+    fn is_same(&self, other: &ReportedFinding) -> bool {
+        self.kind == other.kind
+            && self.resource_kind == other.resource_kind
+            && self.application == other.application
+            && self.chart == other.chart
+            && self.current == other.current
+            && self.latest == other.latest
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, JsonSchema)]
+pub struct ReportedError {
+    #[serde(default)]
+    pub reason: String,
+    #[serde(rename = "resourceKind")]
+    pub resource_kind: String,
+    pub application: String,
+    pub chart: String,
+    pub repo: String,
+    pub message: String,
+}
+
+impl ChartUpdateReportSpec {
+    pub fn preserve_first_seen(&mut self, previous: &ChartUpdateReportSpec) {
+        for finding in self.findings.iter_mut() {
+            if let Some(p) = previous.findings.iter().find(|p| p.is_same(finding)) {
+                finding.first_seen = p.first_seen;
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParentResource {
+    pub kind: String,
+    pub namespace: Option<String>,
+    pub name: String,
+}
+
+impl Display for ParentResource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.namespace {
+            Some(namespace) => write!(f, "{}/{}/{}", self.kind, namespace, self.name),
+            None => write!(f, "{}/{}", self.kind, self.name),
+        }
+    }
+}
+
+pub fn parent_resource<K: ResourceExt>(resource: &K) -> Option<ParentResource> {
+    let owner = resource
+        .owner_references()
+        .iter()
+        .find(|o| o.api_version.starts_with("argoproj.io/"));
+
+    if let Some(owner) = owner {
+        return Some(ParentResource {
+            kind: owner.kind.clone(),
+            namespace: None,
+            name: owner.name.clone(),
+        });
+    }
+
+    let (tracking_app, _) = resource
+        .annotations()
+        .get(TRACKING_ID_ANNOTATION)?
+        .split_once(':')?;
+
+    let (namespace, name) = match tracking_app.split_once('_') {
+        Some((namespace, name)) => (Some(namespace.to_string()), name.to_string()),
+        None => (None, tracking_app.to_string()),
+    };
+
+    if name == resource.name_any() && (namespace.is_none() || namespace == resource.namespace()) {
+        return None;
+    }
+
+    Some(ParentResource {
+        kind: "Application".to_string(),
+        namespace,
+        name,
+    })
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, JsonSchema)]
+pub struct ApplicationStatus {
+    pub sync: Option<SyncStatus>,
+    pub health: Option<HealthStatus>,
+    pub history: Option<Vec<RevisionHistory>>,
+    #[serde(rename = "operationState")]
+    pub operation_state: Option<OperationState>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, JsonSchema)]
+pub struct SyncStatus {
+    pub status: Option<String>,
+    pub revision: Option<String>,
+    pub revisions: Option<Vec<String>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, JsonSchema)]
+pub struct HealthStatus {
+    pub status: Option<String>,
+    pub message: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, JsonSchema)]
+pub struct RevisionHistory {
+    pub id: Option<i64>,
+    pub revision: Option<String>,
+    pub revisions: Option<Vec<String>>,
+    #[serde(rename = "deployedAt")]
+    pub deployed_at: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, JsonSchema)]
+pub struct SyncOperation {
+    pub revision: Option<String>,
+    pub revisions: Option<Vec<String>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, JsonSchema)]
+pub struct Operation {
+    pub sync: Option<SyncOperation>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, JsonSchema)]
+pub struct OperationState {
+    pub operation: Option<Operation>,
+    pub phase: Option<String>,
+    pub message: Option<String>,
+    #[serde(rename = "startedAt")]
+    pub started_at: Option<String>,
+    #[serde(rename = "finishedAt")]
+    pub finished_at: Option<String>,
+}
+
+impl OperationState {
+    pub fn is_completed(&self) -> bool {
+        matches!(
+            self.phase.as_deref(),
+            Some("Succeeded" | "Failed" | "Error")
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SyncOutcome {
+    Succeeded,
+    Failed(String),
+    TimedOut,
+}
+
+impl SyncOutcome {
+    // a sync that did not finish in time may still be running, patching it again would race with it
+    pub fn needs_rollback(&self, degraded: bool) -> bool {
+        match self {
+            SyncOutcome::Succeeded => degraded,
+            SyncOutcome::Failed(_) => true,
+            SyncOutcome::TimedOut => false,
+        }
+    }
+}
+
+impl Display for SyncOutcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SyncOutcome::Succeeded => write!(f, "succeeded"),
+            SyncOutcome::Failed(message) => write!(f, "failed: {}", message),
+            SyncOutcome::TimedOut => write!(f, "timed out"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyncReport {
+    pub outcome: SyncOutcome,
+    pub rollback: Option<(String, SyncOutcome)>,
+}
+
+impl Display for SyncReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.rollback {
+            Some((revision, rollback)) => write!(
+                f,
+                "sync {}, rolled back to {} (sync {})",
+                self.outcome, revision, rollback
+            ),
+            None => write!(f, "sync {}", self.outcome),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RevisionDrift {
+    pub deployed: Option<String>,
+    pub sync_status: Option<String>,
+    pub operation_phase: Option<String>,
+}
+
+impl Application {
+    pub fn helm_in_source(&self) -> bool {
+        self.spec.helm_in_source()
+    }
+
+    pub fn helm_in_sources(&self) -> bool {
+        self.spec.helm_in_sources()
+    }
+
+    pub fn contains_helm(&self) -> bool {
+        self.spec.contains_helm()
+    }
+
+    pub fn deployed_revision(&self, source_index: Option<usize>) -> Option<String> {
+        let last_deployment = self
+            .status
+            .as_ref()?
+            .history
+            .as_ref()?
+            .iter()
+            .max_by_key(|h| h.id.unwrap_or_default())?;
+
+        source_revision(
+            &last_deployment.revision,
+            &last_deployment.revisions,
+            source_index,
+        )
+    }
+
+    fn synced_revision(&self, source_index: Option<usize>) -> Option<String> {
+        let sync = self.status.as_ref()?.sync.as_ref()?;
+
+        source_revision(&sync.revision, &sync.revisions, source_index)
+    }
+
+    fn operation_revision(&self, source_index: Option<usize>) -> Option<String> {
+        let sync = self
+            .status
+            .as_ref()?
+            .operation_state
+            .as_ref()?
+            .operation
+            .as_ref()?
+            .sync
+            .as_ref()?;
+
+        source_revision(&sync.revision, &sync.revisions, source_index)
+    }
+
+    pub fn sync_status(&self) -> Option<String> {
+        self.status.as_ref()?.sync.as_ref()?.status.clone()
+    }
+
+    pub fn is_degraded(&self) -> bool {
+        self.status
+            .as_ref()
+            .and_then(|s| s.health.as_ref())
+            .and_then(|h| h.status.as_deref())
+            == Some("Degraded")
+    }
+
+    pub fn operation_phase(&self) -> Option<String> {
+        self.status
+            .as_ref()?
+            .operation_state
+            .as_ref()?
+            .phase
+            .clone()
+    }
+
+    pub fn revision_drift(
+        &self,
+        target_revision: &str,
+        source_index: Option<usize>,
+    ) -> Option<RevisionDrift> {
+        self.status.as_ref()?;
+
+        let deployed = self.deployed_revision(source_index);
+        let sync_status = self.sync_status();
+        let operation_phase = self.operation_phase();
+
+        // an OutOfSync status alone may be manual drift of a resource, only revisions count
+        let differs = |revision: Option<&String>| revision.is_some_and(|r| r != target_revision);
+        let revision_differs =
+            differs(deployed.as_ref()) || differs(self.synced_revision(source_index).as_ref());
+        let has_failed_sync = matches!(operation_phase.as_deref(), Some("Failed" | "Error"))
+            && self.operation_revision(source_index).as_deref() == Some(target_revision);
+
+        if !revision_differs && !has_failed_sync {
+            return None;
+        }
+
+        Some(RevisionDrift {
+            deployed,
+            sync_status,
+            operation_phase,
+        })
+    }
+}
+
+fn source_revision(
+    revision: &Option<String>,
+    revisions: &Option<Vec<String>>,
+    source_index: Option<usize>,
+) -> Option<String> {
+    match source_index {
+        Some(index) => revisions.as_ref()?.get(index).cloned(),
+        None => revision.clone(),
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
@@ -103,9 +492,19 @@ impl SourceSpec {
     }
 }
 
+fn kubernetes_error(resource: &str, error: kube::Error) -> CheckError {
+    CheckError::Kubernetes {
+        resource: resource.to_string(),
+        message: error.to_string(),
+    }
+}
+
 pub async fn list_applications(client: &Client) -> anyhow::Result<Vec<Application>> {
     let apps_api: Api<Application> = Api::default_namespaced(client.clone());
-    let apps = apps_api.list(&ListParams::default()).await?;
+    let apps = apps_api
+        .list(&ListParams::default())
+        .await
+        .map_err(|e| kubernetes_error("applications", e))?;
 
     let mut output = Vec::new();
 
@@ -116,6 +515,97 @@ pub async fn list_applications(client: &Client) -> anyhow::Result<Vec<Applicatio
     Ok(output)
 }
 
+pub async fn list_application_sets(client: &Client) -> anyhow::Result<Vec<ApplicationSet>> {
+    let app_sets_api: Api<ApplicationSet> = Api::default_namespaced(client.clone());
+    let app_sets = app_sets_api
+        .list(&ListParams::default())
+        .await
+        .map_err(|e| kubernetes_error("application sets", e))?;
+
+    Ok(app_sets.items)
+}
+
+fn secret_value(secret: &Secret, key: &str) -> Option<String> {
+    if let Some(value) = secret.data.as_ref().and_then(|data| data.get(key)) {
+        return String::from_utf8(value.0.clone()).ok();
+    }
+
+    secret.string_data.as_ref()?.get(key).cloned()
+}
+
+pub fn repo_options(secret: &Secret) -> Option<RepoOptions> {
+    if secret_value(secret, "type").as_deref() != Some("helm") {
+        return None;
+    }
+
+    let client_cert = match (
+        secret_value(secret, "tlsClientCertData"),
+        secret_value(secret, "tlsClientCertKey"),
+    ) {
+        (Some(cert), Some(key)) => Some(ClientCert { cert, key }),
+        _ => None,
+    };
+
+    Some(RepoOptions {
+        url: secret_value(secret, "url")?,
+        insecure: secret_value(secret, "insecure").as_deref() == Some("true"),
+        client_cert,
+    })
+}
+
+pub async fn list_repo_options(client: &Client) -> anyhow::Result<Vec<RepoOptions>> {
+    let secrets_api: Api<Secret> = Api::default_namespaced(client.clone());
+    let secrets = secrets_api
+        .list(
+            &ListParams::default()
+                .labels(&format!("{} in (repository,repo-creds)", SECRET_TYPE_LABEL)),
+        )
+        .await
+        .map_err(|e| kubernetes_error("repository secrets", e))?;
+
+    Ok(secrets.items.iter().filter_map(repo_options).collect())
+}
+
+pub async fn get_secret_value(client: &Client, name: &str, key: &str) -> anyhow::Result<String> {
+    let secrets_api: Api<Secret> = Api::default_namespaced(client.clone());
+    let secret = secrets_api
+        .get(name)
+        .await
+        .map_err(|e| kubernetes_error("secrets", e))?;
+
+    match secret_value(&secret, key) {
+        Some(value) => Ok(value),
+        None => bail!("secret {} has no key {}", name, key),
+    }
+}
+
+pub async fn patch_application_set(
+    client: &Client,
+    application_set: &ApplicationSet,
+    helm: &HelmChart,
+    new_revision: &str,
+) -> Result<ApplicationSet, kube::Error> {
+    let app_sets_api: Api<ApplicationSet> = Api::default_namespaced(client.clone());
+
+    let patch = json!({
+        "spec": {
+            "template": {
+                "spec": get_source_patch(&application_set.spec.template.spec, helm, new_revision)
+            }
+        }
+    });
+
+    debug!("{}", patch);
+
+    app_sets_api
+        .patch(
+            &application_set.name_any(),
+            &PatchParams::apply("argo-helm-updater"),
+            &Patch::Merge(patch),
+        )
+        .await
+}
+
 pub async fn patch_application(
     client: &Client,
     argo_application: &Application,
@@ -137,29 +627,271 @@ pub async fn patch_application(
         .await
 }
 
-fn get_application_patch(
+pub async fn apply_chart_update_report(
+    client: &Client,
+    namespace: &str,
+    mut spec: ChartUpdateReportSpec,
+) -> anyhow::Result<()> {
+    let reports_api: Api<ChartUpdateReport> = Api::namespaced(client.clone(), namespace);
+
+    if let Some(previous) = reports_api.get_opt(REPORT_NAME).await? {
+        spec.preserve_first_seen(&previous.spec);
+    }
+
+    let report = ChartUpdateReport::new(REPORT_NAME, spec);
+
+    reports_api
+        .patch(
+            REPORT_NAME,
+            &PatchParams::apply("argo-helm-updater").force(),
+            &Patch::Apply(report),
+        )
+        .await?;
+
+    Ok(())
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutdatedChart {
+    pub chart: String,
+    pub current: String,
+    pub latest: String,
+}
+
+pub fn latest_available(charts: &[OutdatedChart]) -> Option<String> {
+    if charts.is_empty() {
+        return None;
+    }
+
+    Some(
+        charts
+            .iter()
+            .map(|c| format!("{}={}", c.chart, c.latest))
+            .collect::<Vec<String>>()
+            .join(","),
+    )
+}
+
+pub async fn write_back_findings(
+    client: &Client,
+    argo_application: &Application,
+    charts: &[OutdatedChart],
+) -> anyhow::Result<()> {
+    let value = latest_available(charts);
+    let announced = argo_application
+        .annotations()
+        .get(LATEST_AVAILABLE_ANNOTATION)
+        .cloned();
+
+    if announced == value {
+        return Ok(());
+    }
+
+    let announced: Vec<&str> = announced
+        .as_deref()
+        .map(|a| a.split(',').collect())
+        .unwrap_or_default();
+
+    let recorder = Recorder::new(
+        client.clone(),
+        Reporter {
+            controller: "argo-helm-updater".to_string(),
+            instance: None,
+        },
+    );
+
+    for chart in charts {
+        if announced.contains(&format!("{}={}", chart.chart, chart.latest).as_str()) {
+            continue;
+        }
+
+        recorder
+            .publish(
+                &Event {
+                    type_: EventType::Normal,
+                    reason: OUTDATED_REASON.to_string(),
+                    note: Some(format!(
+                        "chart {} has new version {} (current: {})",
+                        chart.chart, chart.latest, chart.current
+                    )),
+                    action: "CheckChartVersion".to_string(),
+                    secondary: None,
+                },
+                &argo_application.object_ref(&()),
+            )
+            .await?;
+    }
+
+    let apps_api: Api<Application> = Api::default_namespaced(client.clone());
+
+    let patch = json!({
+        "metadata": {
+            "annotations": {
+                LATEST_AVAILABLE_ANNOTATION: value,
+            },
+        },
+    });
+
+    debug!("{}", patch);
+
+    apps_api
+        .patch(
+            &argo_application.name_any(),
+            &PatchParams::apply("argo-helm-updater"),
+            &Patch::Merge(patch),
+        )
+        .await?;
+
+    Ok(())
+}
+
+pub async fn sync_application(
+    client: &Client,
     argo_application: &Application,
     helm: &HelmChart,
     new_revision: &str,
-) -> Value {
-    if argo_application.helm_in_sources() {
-        let sources = argo_application.spec.sources.clone().unwrap();
-        let mut patched_sources: Vec<SourceSpec> = Vec::new();
+    timeout: Duration,
+) -> anyhow::Result<SyncOutcome> {
+    let apps_api: Api<Application> = Api::default_namespaced(client.clone());
+    let name = argo_application.name_any();
 
-        for source in sources {
-            let mut patched_source = source.clone();
+    let previous_start = apps_api
+        .get(&name)
+        .await?
+        .status
+        .and_then(|s| s.operation_state)
+        .and_then(|o| o.started_at);
 
-            if source.chart.is_some() && helm.chart.cmp(&(source.clone()).chart.unwrap()).is_eq() {
-                patched_source.target_revision = Some((*new_revision).to_string());
-            }
+    let patch = get_sync_patch(argo_application, helm, new_revision);
+
+    debug!("{}", patch);
+
+    apps_api
+        .patch(
+            &name,
+            &PatchParams::apply("argo-helm-updater"),
+            &Patch::Merge(patch),
+        )
+        .await?;
+
+    info!(
+        "requested sync of application '{}' to {}",
+        name, new_revision
+    );
+
+    wait_for_operation(&apps_api, &name, previous_start, timeout).await
+}
+
+async fn wait_for_operation(
+    apps_api: &Api<Application>,
+    name: &str,
+    previous_start: Option<String>,
+    timeout: Duration,
+) -> anyhow::Result<SyncOutcome> {
+    let deadline = tokio::time::Instant::now() + timeout;
+
+    while tokio::time::Instant::now() < deadline {
+        tokio::time::sleep(Duration::from_secs(2)).await;
+
+        let app = apps_api.get(name).await?;
+        let operation_state = app.status.and_then(|s| s.operation_state);
+
+        let operation_state = match operation_state {
+            Some(o) if o.started_at != previous_start && o.is_completed() => o,
+            _ => continue,
+        };
+
+        return match operation_state.phase.as_deref() {
+            Some("Succeeded") => Ok(SyncOutcome::Succeeded),
+            _ => Ok(SyncOutcome::Failed(
+                operation_state.message.unwrap_or_default(),
+            )),
+        };
+    }
+
+    Ok(SyncOutcome::TimedOut)
+}
+
+pub async fn wait_for_degraded(
+    client: &Client,
+    argo_application: &Application,
+    window: Duration,
+) -> anyhow::Result<Option<HealthStatus>> {
+    let apps_api: Api<Application> = Api::default_namespaced(client.clone());
+    let name = argo_application.name_any();
+    let deadline = tokio::time::Instant::now() + window;
 
-            patched_sources.push(patched_source);
+    while tokio::time::Instant::now() < deadline {
+        tokio::time::sleep(Duration::from_secs(5)).await;
+
+        let app = apps_api.get(&name).await?;
+
+        if app.is_degraded() {
+            return Ok(app.status.and_then(|s| s.health));
         }
+    }
+
+    Ok(None)
+}
+
+fn get_sync_patch(argo_application: &Application, helm: &HelmChart, new_revision: &str) -> Value {
+    let sync = if argo_application.helm_in_sources() {
+        let revisions: Vec<String> =
+            get_patched_sources(&argo_application.spec, helm, new_revision)
+                .into_iter()
+                .map(|s| s.target_revision.unwrap_or_default())
+                .collect();
+
+        json!({ "revisions": revisions })
+    } else {
+        json!({ "revision": new_revision })
+    };
+
+    json!({
+        "operation": {
+            "initiatedBy": { "username": "argo-helm-updater" },
+            "sync": sync,
+        }
+    })
+}
 
-        return json!({ "spec": { "sources": patched_sources } });
+fn get_patched_sources(
+    spec: &ApplicationSpec,
+    helm: &HelmChart,
+    new_revision: &str,
+) -> Vec<SourceSpec> {
+    let sources = spec.sources.clone().unwrap();
+    let mut patched_sources: Vec<SourceSpec> = Vec::new();
+
+    for source in sources {
+        let mut patched_source = source.clone();
+
+        if source.chart.is_some() && helm.chart.cmp(&(source.clone()).chart.unwrap()).is_eq() {
+            patched_source.target_revision = Some((*new_revision).to_string());
+        }
+
+        patched_sources.push(patched_source);
     }
 
-    let mut patched_source = argo_application.spec.source.clone().unwrap();
+    patched_sources
+}
+
+fn get_application_patch(
+    argo_application: &Application,
+    helm: &HelmChart,
+    new_revision: &str,
+) -> Value {
+    json!({ "spec": get_source_patch(&argo_application.spec, helm, new_revision) })
+}
+
+fn get_source_patch(spec: &ApplicationSpec, helm: &HelmChart, new_revision: &str) -> Value {
+    if spec.helm_in_sources() {
+        let patched_sources = get_patched_sources(spec, helm, new_revision);
+
+        return json!({ "sources": patched_sources });
+    }
+
+    let mut patched_source = spec.source.clone().unwrap();
 
     if helm
         .chart
@@ -169,5 +901,267 @@ fn get_application_patch(
         patched_source.target_revision = Some(new_revision.to_string());
     }
 
-    json!({ "spec": { "source": patched_source } })
+    json!({ "source": patched_source })
+}
+
+#[cfg(test)]
+mod test {
+    use k8s_openapi::apimachinery::pkg::apis::meta::v1::OwnerReference;
+    use kube::ResourceExt;
+    use serde_json::json;
+
+    use crate::helm::{ClientCert, HelmChart, RepoOptions};
+
+    use super::{
+        get_sync_patch, latest_available, parent_resource, repo_options, Application,
+        OutdatedChart, ReportedError, RevisionDrift, SyncOutcome,
+    };
+
+    fn init_application(status: serde_json::Value) -> Application {
+        serde_json::from_value(json!({
+            "apiVersion": "argoproj.io/v1alpha1",
+            "kind": "Application",
+            "metadata": { "name": "app", "namespace": "argocd" },
+            "spec": {
+                "project": "default",
+                "source": {
+                    "chart": "chart",
+                    "repoURL": "https://charts.example.com",
+                    "targetRevision": "1.1.0"
+                }
+            },
+            "status": status,
+        }))
+        .expect("invalid application")
+    }
+
+    #[test]
+    fn application_revision_drift_none_when_deployed() {
+        let app = init_application(json!({
+            "sync": { "status": "Synced", "revision": "1.1.0" },
+            "history": [
+                { "id": 1, "revision": "1.0.0" },
+                { "id": 2, "revision": "1.1.0" },
+            ],
+        }));
+
+        assert_eq!(None, app.revision_drift("1.1.0", None));
+    }
+
+    #[test]
+    fn application_revision_drift_on_failed_sync() {
+        let app = init_application(json!({
+            "sync": { "status": "OutOfSync", "revision": "1.1.0" },
+            "history": [{ "id": 1, "revision": "1.0.0" }],
+            "operationState": { "phase": "Failed", "operation": { "sync": { "revision": "1.1.0" } } },
+        }));
+
+        let expected = RevisionDrift {
+            deployed: Some("1.0.0".to_owned()),
+            sync_status: Some("OutOfSync".to_owned()),
+            operation_phase: Some("Failed".to_owned()),
+        };
+
+        assert_eq!(Some(expected), app.revision_drift("1.1.0", None));
+    }
+
+    #[test]
+    fn application_revision_drift_none_when_out_of_sync_on_target() {
+        let app = init_application(json!({
+            "sync": { "status": "OutOfSync", "revision": "1.1.0" },
+            "history": [{ "id": 1, "revision": "1.1.0" }],
+        }));
+
+        assert_eq!(None, app.revision_drift("1.1.0", None));
+    }
+
+    #[test]
+    fn application_revision_drift_on_failed_sync_of_target() {
+        let app = init_application(json!({
+            "sync": { "status": "Synced", "revisions": ["abc", "1.1.0"] },
+            "history": [{ "id": 1, "revisions": ["abc", "1.1.0"] }],
+            "operationState": {
+                "phase": "Failed",
+                "operation": { "sync": { "revisions": ["def", "1.1.0"] } },
+            },
+        }));
+
+        assert!(app.revision_drift("1.1.0", Some(1)).is_some());
+        assert!(app.revision_drift("abc", Some(0)).is_none());
+    }
+
+    #[test]
+    fn application_deployed_revision_multi_source() {
+        let app = init_application(json!({
+            "history": [
+                { "id": 2, "revisions": ["abc", "2.0.0"] },
+                { "id": 1, "revisions": ["abc", "1.0.0"] },
+            ],
+        }));
+
+        assert_eq!(Some("2.0.0".to_owned()), app.deployed_revision(Some(1)));
+        assert!(app.revision_drift("2.0.0", Some(1)).is_none());
+        assert!(app.revision_drift("2.1.0", Some(1)).is_some());
+    }
+
+    #[test]
+    fn get_sync_patch_multi_source_revisions() {
+        let app: Application = serde_json::from_value(json!({
+            "apiVersion": "argoproj.io/v1alpha1",
+            "kind": "Application",
+            "metadata": { "name": "app", "namespace": "argocd" },
+            "spec": {
+                "project": "default",
+                "sources": [
+                    { "repoURL": "https://git.example.com/values", "targetRevision": "main", "ref": "values" },
+                    { "chart": "chart", "repoURL": "https://charts.example.com", "targetRevision": "1.0.0" },
+                ]
+            },
+        }))
+        .expect("invalid application");
+
+        let helm = HelmChart {
+            chart: "chart".to_owned(),
+            repo: "https://charts.example.com".to_owned(),
+            revision: "1.0.0".to_owned(),
+        };
+
+        let patch = get_sync_patch(&app, &helm, "1.1.0");
+
+        assert_eq!(
+            json!(["main", "1.1.0"]),
+            patch["operation"]["sync"]["revisions"]
+        );
+    }
+
+    #[test]
+    fn parent_resource_from_owner_reference() {
+        let mut app = init_application(json!({}));
+        assert_eq!(None, parent_resource(&app));
+
+        app.metadata.owner_references = Some(vec![OwnerReference {
+            api_version: "argoproj.io/v1alpha1".to_owned(),
+            kind: "ApplicationSet".to_owned(),
+            name: "cluster-addons".to_owned(),
+            uid: "uid".to_owned(),
+            ..OwnerReference::default()
+        }]);
+
+        let parent = parent_resource(&app).expect("missing parent");
+        assert_eq!("ApplicationSet/cluster-addons", parent.to_string());
+    }
+
+    #[test]
+    fn parent_resource_from_tracking_id() {
+        let mut app = init_application(json!({}));
+
+        app.annotations_mut().insert(
+            "argocd.argoproj.io/tracking-id".to_owned(),
+            "app:argoproj.io/Application:argocd/app".to_owned(),
+        );
+        assert_eq!(None, parent_resource(&app));
+
+        app.annotations_mut().insert(
+            "argocd.argoproj.io/tracking-id".to_owned(),
+            "root_apps:argoproj.io/Application:argocd/app".to_owned(),
+        );
+
+        let parent = parent_resource(&app).expect("missing parent");
+        assert_eq!("Application/root/apps", parent.to_string());
+    }
+
+    #[test]
+    fn latest_available_annotation_value() {
+        assert_eq!(None, latest_available(&[]));
+
+        let charts = vec![
+            OutdatedChart {
+                chart: "loki".to_owned(),
+                current: "5.0.0".to_owned(),
+                latest: "5.1.0".to_owned(),
+            },
+            OutdatedChart {
+                chart: "promtail".to_owned(),
+                current: "6.0.0".to_owned(),
+                latest: "6.2.0".to_owned(),
+            },
+        ];
+
+        assert_eq!(
+            Some("loki=5.1.0,promtail=6.2.0".to_owned()),
+            latest_available(&charts)
+        );
+    }
+
+    #[test]
+    fn repo_options_from_argo_secret() {
+        let secret = serde_json::from_value(json!({
+            "apiVersion": "v1",
+            "kind": "Secret",
+            "metadata": { "name": "charts", "namespace": "argocd" },
+            "data": {
+                "type": "aGVsbQ==",
+                "url": "aHR0cHM6Ly9jaGFydHMuZXhhbXBsZS5jb20=",
+            },
+            "stringData": {
+                "insecure": "true",
+                "tlsClientCertData": "cert",
+                "tlsClientCertKey": "key",
+            },
+        }))
+        .expect("invalid secret");
+
+        assert_eq!(
+            Some(RepoOptions {
+                url: "https://charts.example.com".to_owned(),
+                insecure: true,
+                client_cert: Some(ClientCert {
+                    cert: "cert".to_owned(),
+                    key: "key".to_owned(),
+                }),
+            }),
+            repo_options(&secret)
+        );
+
+        let git = serde_json::from_value(json!({
+            "apiVersion": "v1",
+            "kind": "Secret",
+            "metadata": { "name": "git", "namespace": "argocd" },
+            "stringData": { "url": "https://github.com/example/gitops" },
+        }))
+        .expect("invalid secret");
+
+        assert_eq!(None, repo_options(&git));
+    }
+
+    #[test]
+    fn application_is_degraded() {
+        assert!(init_application(json!({ "health": { "status": "Degraded" } })).is_degraded());
+        assert!(!init_application(json!({ "health": { "status": "Healthy" } })).is_degraded());
+        assert!(!init_application(json!({ "health": {} })).is_degraded());
+        assert!(!init_application(json!({})).is_degraded());
+    }
+
+    #[test]
+    fn sync_outcome_needs_rollback() {
+        assert!(!SyncOutcome::Succeeded.needs_rollback(false));
+        assert!(SyncOutcome::Succeeded.needs_rollback(true));
+        assert!(SyncOutcome::Failed("hook failed".to_owned()).needs_rollback(false));
+        assert!(!SyncOutcome::TimedOut.needs_rollback(false));
+    }
+
+    #[test]
+    fn reported_error_without_reason() {
+        let error: ReportedError = serde_json::from_value(json!({
+            "resourceKind": "Application",
+            "application": "loki",
+            "chart": "loki",
+            "repo": "https://grafana.github.io/helm-charts",
+            "message": "repository unreachable",
+        }))
+        .unwrap();
+
+        assert_eq!("", error.reason);
+        assert_eq!("loki", error.application);
+    }
 }
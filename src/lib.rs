@@ -0,0 +1,3 @@
+pub mod error;
+pub mod helm;
+pub mod kubernetes;
@@ -1,19 +1,85 @@
+use std::{
+    io::IsTerminal,
+    path::{Path, PathBuf},
+    process::ExitCode,
+    time::Duration,
+};
+
 use anyhow::bail;
-use clap::Parser;
-use helm::HelmRepoReqwestClient;
+use argo_helm_updater::{error, helm, kubernetes};
+use chrono::Utc;
+use clap::{Parser, Subcommand};
+use controller::{watch, WatchArgs};
+use git::{commit_updates, CommitMode, GitOptions};
+use helm::{
+    CachedHelmRepoClient, ChartUpdate, ClientCert, HelmRepoClient, HelmRepoReqwestClient,
+    HttpOptions, IndexPath, RepoOptions,
+};
 use inquire::Confirm;
-use kube::{Client, ResourceExt};
-use kubernetes::{init_client, patch_application, Application, SourceSpec};
-use log::{error, info};
+use kube::{Client, CustomResourceExt, ResourceExt};
+use kubernetes::{
+    apply_chart_update_report, get_secret_value, init_client, list_application_sets,
+    list_repo_options, parent_resource, patch_application, patch_application_set, sync_application,
+    wait_for_degraded, write_back_findings, Application, ApplicationSet, ChartUpdateReport,
+    OutdatedChart, ParentResource, SourceSpec, SyncOutcome, SyncReport,
+};
+use log::{debug, error, info, warn};
+use manifest::{
+    scan_directory, target_revision_path, update_manifest, ManifestApplication, ManifestChart,
+};
+use notify::{Notifier, Webhook};
+use output::{render_junit, render_markdown, render_sarif, render_table, GroupBy, OutputFormat};
+use report::{FailOn, Finding, FindingKind, Report, ScanError, ScannedSource, EXIT_SCAN_FAILED};
+use serve::{serve, ServeArgs};
+use values::{resolve_values, Checkout, Checkouts};
 
 use crate::{helm::HelmChart, kubernetes::list_applications};
 
-mod helm;
-mod kubernetes;
+mod controller;
+mod git;
+mod manifest;
+mod metrics;
+mod notify;
+mod output;
+mod report;
+mod serve;
+mod values;
+mod yaml;
 
-#[derive(Parser, Debug)]
+#[derive(Subcommand, Debug, Clone)]
+enum Command {
+    #[command(
+        about = "Periodically check all charts and expose the results as prometheus metrics"
+    )]
+    Serve(ServeArgs),
+    #[command(
+        about = "Watch applications and check them whenever they change or their helm repository index expires"
+    )]
+    Watch(WatchArgs),
+    #[command(about = "Print the ChartUpdateReport custom resource definition")]
+    Crd,
+}
+
+#[derive(Parser, Debug, Clone)]
 #[command(author, version, about, long_about = None)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[arg(
+        long,
+        conflicts_with_all = ["namespace", "context", "sync", "force"],
+        help = "Check the Application manifests in the given directory instead of the cluster"
+    )]
+    from_dir: Option<PathBuf>,
+
+    #[arg(
+        long,
+        requires = "from_dir",
+        help = "Local checkout of a repository referenced as $ref value source, as <repoURL>=<path>. Defaults to --from-dir"
+    )]
+    checkout: Vec<Checkout>,
+
     #[arg(short, long, help = "Namespace that holds all applications to check")]
     namespace: Option<String>,
 
@@ -23,119 +89,1118 @@ struct Args {
     #[arg(
         long,
         default_value_t = false,
-        help = "Prompt to update the application directly in the cluster"
+        help = "Prompt to update the application directly in the cluster, or in its manifest with --from-dir"
     )]
     update: bool,
+
+    #[arg(
+        long,
+        default_value_t = false,
+        requires = "update",
+        help = "Apply all updates without prompting for confirmation"
+    )]
+    yes: bool,
+
+    #[arg(
+        long,
+        value_enum,
+        requires_all = ["from_dir", "update"],
+        help = "Commit manifest updates to new git branches, one per update or grouped into one"
+    )]
+    git_commit: Option<CommitMode>,
+
+    #[arg(
+        long,
+        default_value = "argo-helm-updater/{app}-{chart}-{new}",
+        help = "Branch name template for --git-commit per-update"
+    )]
+    branch_template: String,
+
+    #[arg(
+        long,
+        default_value = "argo-helm-updater/chart-updates",
+        help = "Branch name for --git-commit grouped"
+    )]
+    group_branch: String,
+
+    #[arg(
+        long,
+        default_value = "Update {chart} of {app} from {old} to {new}\n\n{changelog}",
+        help = "Commit message template, supports {app}, {namespace}, {chart}, {old}, {new} and {changelog}"
+    )]
+    commit_template: String,
+
+    #[arg(
+        long,
+        default_value_t = false,
+        requires_all = ["from_dir", "update"],
+        help = "Run `helm dependency update` after updating a dependency in a Chart.yaml"
+    )]
+    refresh_chart_lock: bool,
+
+    #[arg(
+        long,
+        default_value_t = false,
+        requires = "update",
+        help = "Request an argo sync of the new revision after updating an application"
+    )]
+    sync: bool,
+
+    #[arg(
+        long,
+        default_value_t = 300,
+        help = "Seconds to wait for a requested sync to finish"
+    )]
+    sync_timeout: u64,
+
+    #[arg(
+        long,
+        default_value_t = false,
+        requires = "sync",
+        help = "Roll back to the previous revision when the sync fails or the application becomes degraded"
+    )]
+    rollback: bool,
+
+    #[arg(
+        long,
+        default_value_t = 120,
+        help = "Seconds to watch the application health after a sync before keeping the update"
+    )]
+    rollback_window: u64,
+
+    #[arg(
+        long,
+        default_value_t = false,
+        requires = "update",
+        help = "Update resources even if they are managed by an ApplicationSet or a parent application"
+    )]
+    force: bool,
+
+    #[arg(
+        long,
+        default_value_t = false,
+        conflicts_with = "from_dir",
+        help = "Emit events and maintain the argo-helm-updater/latest-available annotation on outdated applications"
+    )]
+    write_back: bool,
+
+    #[arg(
+        long,
+        default_value_t = false,
+        conflicts_with = "from_dir",
+        help = "Upsert a ChartUpdateReport with the findings into every scanned namespace"
+    )]
+    publish_report: bool,
+
+    #[arg(
+        long,
+        help = "Send a digest of new and resolved findings to a webhook, as <slack|teams|json>=<url>"
+    )]
+    webhook: Vec<Webhook>,
+
+    #[arg(
+        long,
+        default_value = ".argo-helm-updater-notified.json",
+        help = "File that remembers the findings already sent to the webhooks"
+    )]
+    notify_state: PathBuf,
+
+    #[arg(long, value_enum, default_value_t = OutputFormat::Table, help = "Format of the findings printed to stdout")]
+    output: OutputFormat,
+
+    #[arg(long, value_enum, default_value_t = GroupBy::Namespace, help = "Group the table output by namespace or chart")]
+    group_by: GroupBy,
+
+    #[arg(
+        long,
+        value_enum,
+        help = "Only exit with an error code for updates of at least patch, minor or major, for any update, or only for errors"
+    )]
+    fail_on: Option<FailOn>,
+
+    #[arg(
+        long,
+        default_value_t = 10,
+        help = "Seconds to wait for a connection to a helm repository"
+    )]
+    connect_timeout: u64,
+
+    #[arg(
+        long,
+        default_value_t = 30,
+        help = "Seconds to wait for data from a helm repository before the request fails"
+    )]
+    read_timeout: u64,
+
+    #[arg(
+        long,
+        default_value_t = 3,
+        help = "Retries with exponential backoff when a helm repository responds with 5xx or 429 or times out"
+    )]
+    retries: u32,
+
+    #[arg(
+        long,
+        help = "PEM bundle with additional root certificates to trust for helm repositories"
+    )]
+    ca_file: Vec<PathBuf>,
+
+    #[arg(
+        long,
+        conflicts_with = "from_dir",
+        help = "Secret with additional root certificates in its ca.crt key"
+    )]
+    ca_secret: Vec<String>,
+
+    #[arg(
+        long,
+        requires = "client_key",
+        help = "PEM client certificate to present to helm repositories"
+    )]
+    client_cert: Option<PathBuf>,
+
+    #[arg(
+        long,
+        requires = "client_cert",
+        help = "PEM private key of --client-cert"
+    )]
+    client_key: Option<PathBuf>,
+
+    #[arg(
+        long,
+        help = "Skip TLS verification for helm repositories starting with the given URL"
+    )]
+    insecure_repo: Vec<String>,
+
+    #[arg(
+        long,
+        env = "HTTPS_PROXY",
+        help = "Proxy for all requests to helm repositories"
+    )]
+    proxy: Option<String>,
+
+    #[arg(
+        long,
+        env = "NO_PROXY",
+        help = "Comma separated hosts, domains and networks to reach without --proxy"
+    )]
+    no_proxy: Option<String>,
+
+    #[arg(
+        long,
+        help = "Path of the index of a helm repository relative to its URL, as <repoURL>=<path>. Defaults to index.yaml"
+    )]
+    index_path: Vec<IndexPath>,
+
+    #[arg(
+        long,
+        help = "Directory to keep fetched helm repository indexes in, revalidated with their ETag or Last-Modified header"
+    )]
+    cache_dir: Option<PathBuf>,
 }
 
-pub async fn verify_helm_source(
-    client: &Client,
+async fn helm_repo_client(
+    args: &Args,
+    client: Option<&Client>,
+) -> anyhow::Result<HelmRepoReqwestClient> {
+    let mut ca_certs = Vec::new();
+    for path in &args.ca_file {
+        ca_certs.push(std::fs::read_to_string(path)?);
+    }
+
+    let mut repos: Vec<RepoOptions> = args
+        .insecure_repo
+        .iter()
+        .map(|url| RepoOptions {
+            url: url.clone(),
+            insecure: true,
+            client_cert: None,
+        })
+        .collect();
+
+    if let Some(client) = client {
+        for name in &args.ca_secret {
+            ca_certs.push(get_secret_value(client, name, "ca.crt").await?);
+        }
+
+        match list_repo_options(client).await {
+            Ok(options) => repos.extend(options),
+            Err(e) => warn!("cannot read argo repository secrets: {:#}", e),
+        }
+    }
+
+    let client_cert = match (&args.client_cert, &args.client_key) {
+        (Some(cert), Some(key)) => Some(ClientCert {
+            cert: std::fs::read_to_string(cert)?,
+            key: std::fs::read_to_string(key)?,
+        }),
+        _ => None,
+    };
+
+    HelmRepoReqwestClient::new(&HttpOptions {
+        connect_timeout: Duration::from_secs(args.connect_timeout),
+        read_timeout: Duration::from_secs(args.read_timeout),
+        retries: args.retries,
+        ca_certs,
+        client_cert,
+        proxy: args.proxy.clone(),
+        no_proxy: args.no_proxy.clone(),
+        repos,
+        index_paths: args.index_path.clone(),
+        cache_dir: args.cache_dir.clone(),
+    })
+}
+
+#[derive(Debug, Clone)]
+pub struct UpdateOptions {
+    pub sync_timeout: Option<Duration>,
+    pub rollback_window: Option<Duration>,
+    pub force: bool,
+    pub yes: bool,
+    pub git: Option<GitOptions>,
+    pub refresh_chart_lock: bool,
+}
+
+impl UpdateOptions {
+    fn from_args(args: &Args) -> Option<Self> {
+        if !args.update {
+            return None;
+        }
+
+        Some(Self {
+            sync_timeout: args.sync.then(|| Duration::from_secs(args.sync_timeout)),
+            rollback_window: args
+                .rollback
+                .then(|| Duration::from_secs(args.rollback_window)),
+            force: args.force,
+            yes: args.yes,
+            git: args.git_commit.map(|mode| GitOptions {
+                mode,
+                branch_template: args.branch_template.clone(),
+                group_branch: args.group_branch.clone(),
+                message_template: args.commit_template.clone(),
+            }),
+            refresh_chart_lock: args.refresh_chart_lock,
+        })
+    }
+}
+
+pub fn verify_deployed_revision(
     argo_application: &Application,
     source_spec: &SourceSpec,
-    should_ask_for_update: bool,
-) -> anyhow::Result<()> {
+    source_index: Option<usize>,
+) -> Option<Finding> {
+    let helm = HelmChart::try_from(source_spec.clone()).ok()?;
+    let drift = argo_application.revision_drift(&helm.revision, source_index)?;
+
+    warn!(
+        "app: {} | chart: {} does not run its target revision {} (deployed: {}, sync: {}, operation: {})",
+        argo_application.name_any(),
+        helm.chart,
+        helm.revision,
+        drift.deployed.as_deref().unwrap_or("unknown"),
+        drift.sync_status.as_deref().unwrap_or("unknown"),
+        drift.operation_phase.as_deref().unwrap_or("none"),
+    );
+
+    Some(Finding::new(
+        argo_application,
+        &helm,
+        FindingKind::RevisionDrift(drift),
+    ))
+}
+
+async fn find_chart_update(
+    helm_client: &dyn HelmRepoClient,
+    source_spec: &SourceSpec,
+) -> anyhow::Result<Option<(HelmChart, ChartUpdate)>> {
     let helm = HelmChart::try_from(source_spec.clone());
 
     if helm.is_err() {
-        return Ok(());
+        return Ok(None);
     }
 
     let helm = helm.unwrap();
-    let newest_version = helm.get_newer_version(&HelmRepoReqwestClient {}).await?;
+    let update = helm.get_chart_update(helm_client).await?;
+
+    Ok(update.map(|u| (helm, u)))
+}
+
+pub async fn verify_manifest_source(
+    helm_client: &dyn HelmRepoClient,
+    manifest: &ManifestApplication,
+    source_spec: &SourceSpec,
+    source_index: Option<usize>,
+    values: &serde_yaml::Value,
+) -> anyhow::Result<Option<Finding>> {
+    if let Some((helm, mut update)) = find_chart_update(helm_client, source_spec).await? {
+        let location = manifest
+            .location
+            .with_yaml_path(target_revision_path(source_index));
+
+        if values.as_mapping().is_some_and(|v| !v.is_empty()) {
+            match helm
+                .get_removed_values(helm_client, &update.version, values)
+                .await
+            {
+                Ok(removed) => update.removed_values = removed,
+                Err(e) => warn!(
+                    "app: {} | cannot compare values with chart {} {}: {:#}",
+                    manifest.application.name_any(),
+                    helm.chart,
+                    update.version,
+                    e
+                ),
+            }
+        }
+
+        for key in &update.removed_values {
+            warn!(
+                "app: {} | value {} is no longer part of chart {} {} | {}",
+                manifest.application.name_any(),
+                key,
+                helm.chart,
+                update.version,
+                manifest.location,
+            );
+        }
+
+        info!(
+            "app: {} | chart: {} has new version {} (current: {}) | {}",
+            manifest.application.name_any(),
+            helm.chart,
+            update.version,
+            helm.revision,
+            manifest.location,
+        );
+
+        return Ok(Some(
+            Finding::new(
+                &manifest.application,
+                &helm,
+                FindingKind::UpdateAvailable(update),
+            )
+            .with_location(location),
+        ));
+    }
+
+    Ok(None)
+}
+
+pub async fn verify_manifest_chart(
+    helm_client: &dyn HelmRepoClient,
+    manifest_chart: &ManifestChart,
+) -> anyhow::Result<Option<Finding>> {
+    let update = manifest_chart.helm.get_chart_update(helm_client).await?;
+
+    if let Some(update) = update {
+        info!(
+            "{}: {} | dependency: {} has new version {} (current: {}) | {}",
+            manifest_chart.kind.to_lowercase(),
+            manifest_chart.name,
+            manifest_chart.helm.chart,
+            update.version,
+            manifest_chart.helm.revision,
+            manifest_chart.location,
+        );
+
+        return Ok(Some(Finding::from_manifest_chart(
+            manifest_chart,
+            FindingKind::UpdateAvailable(update),
+        )));
+    }
+
+    Ok(None)
+}
+
+pub async fn verify_helm_source(
+    client: &Client,
+    helm_client: &dyn HelmRepoClient,
+    argo_application: &Application,
+    source_spec: &SourceSpec,
+    update_options: Option<&UpdateOptions>,
+) -> anyhow::Result<Option<Finding>> {
+    if let Some((helm, mut update)) = find_chart_update(helm_client, source_spec).await? {
+        let newest_version = update.version.clone();
+        let parent = parent_resource(argo_application);
 
-    if let Some(newest_version) = newest_version {
         info!(
-            "app: {} | chart: {} has new version {} (current: {})",
+            "app: {} | chart: {} has new version {} (current: {}){}",
             argo_application.name_any(),
             helm.chart,
             newest_version,
             helm.revision,
+            parent
+                .as_ref()
+                .map(|p| format!(" | managed by {}", p))
+                .unwrap_or_default(),
         );
 
-        if should_ask_for_update {
-            ask_for_update(client, argo_application, &helm, &newest_version).await?;
+        if let Some(update_options) = update_options {
+            if is_update_allowed(argo_application, parent.as_ref(), update_options) {
+                update.sync = ask_for_update(
+                    client,
+                    argo_application,
+                    &helm,
+                    &newest_version,
+                    update_options,
+                )
+                .await?;
+            }
         }
+
+        return Ok(Some(
+            Finding::new(
+                argo_application,
+                &helm,
+                FindingKind::UpdateAvailable(update),
+            )
+            .with_owner(parent.map(|p| p.to_string())),
+        ));
     }
 
-    Ok(())
+    Ok(None)
 }
 
-async fn ask_for_update(
+pub async fn verify_application_set_source(
     client: &Client,
-    argo_application: &Application,
-    helm: &HelmChart,
+    helm_client: &dyn HelmRepoClient,
+    application_set: &ApplicationSet,
+    source_spec: &SourceSpec,
+    update_options: Option<&UpdateOptions>,
+) -> anyhow::Result<Option<Finding>> {
+    if let Some((helm, update)) = find_chart_update(helm_client, source_spec).await? {
+        let newest_version = update.version.clone();
+        let parent = parent_resource(application_set);
+
+        info!(
+            "appset: {} | chart: {} has new version {} (current: {}){}",
+            application_set.name_any(),
+            helm.chart,
+            newest_version,
+            helm.revision,
+            parent
+                .as_ref()
+                .map(|p| format!(" | managed by {}", p))
+                .unwrap_or_default(),
+        );
+
+        if let Some(update_options) = update_options {
+            if is_update_allowed(application_set, parent.as_ref(), update_options)
+                && confirm_update(
+                    &application_set.name_any(),
+                    &helm.revision,
+                    &newest_version,
+                    update_options,
+                )?
+            {
+                patch_application_set(client, application_set, &helm, &newest_version).await?;
+
+                info!("successfully update the application set template");
+            }
+        }
+
+        return Ok(Some(
+            Finding::new(application_set, &helm, FindingKind::UpdateAvailable(update))
+                .with_owner(parent.map(|p| p.to_string())),
+        ));
+    }
+
+    Ok(None)
+}
+
+fn is_update_allowed<K: ResourceExt>(
+    resource: &K,
+    parent: Option<&ParentResource>,
+    update_options: &UpdateOptions,
+) -> bool {
+    let parent = match parent {
+        Some(parent) => parent,
+        None => return true,
+    };
+
+    if update_options.force {
+        warn!(
+            "{} is managed by {}, the update will be reverted unless the source of {} is changed as well",
+            resource.name_any(),
+            parent,
+            parent,
+        );
+
+        return true;
+    }
+
+    warn!(
+        "not updating {}, it is managed by {}. Change the source of {} instead or use --force",
+        resource.name_any(),
+        parent,
+        parent,
+    );
+
+    false
+}
+
+fn confirm_update(
+    name: &str,
+    current_version: &str,
     newest_version: &str,
-) -> anyhow::Result<()> {
+    update_options: &UpdateOptions,
+) -> anyhow::Result<bool> {
+    if update_options.yes {
+        return Ok(true);
+    }
+
     let ans = Confirm::new(&format!(
         "Do you want to update {} from {} to {}?",
-        argo_application.name_any(),
-        helm.revision,
-        newest_version,
+        name, current_version, newest_version,
     ))
     .with_default(false)
     .with_help_message("Don't forget to also update the argo files in your git repo!")
     .prompt();
 
     match ans {
-        Ok(true) => {
-            patch_application(client, argo_application, helm, newest_version).await?;
-
-            info!("successfully update the application spec");
-
-            Ok(())
-        }
+        Ok(true) => Ok(true),
         Ok(false) => {
             info!("not updating the chart");
 
-            Ok(())
+            Ok(false)
         }
         Err(_) => bail!("cannot get user confirmation to update"),
     }
 }
 
-#[tokio::main]
-async fn main() -> anyhow::Result<()> {
-    let args = Args::parse();
-    env_logger::init_from_env(
-        env_logger::Env::default().filter_or(env_logger::DEFAULT_FILTER_ENV, "info"),
-    );
+async fn ask_for_update(
+    client: &Client,
+    argo_application: &Application,
+    helm: &HelmChart,
+    newest_version: &str,
+    update_options: &UpdateOptions,
+) -> anyhow::Result<Option<SyncReport>> {
+    if !confirm_update(
+        &argo_application.name_any(),
+        &helm.revision,
+        newest_version,
+        update_options,
+    )? {
+        return Ok(None);
+    }
 
-    let client = init_client(args.context, args.namespace).await?;
+    patch_application(client, argo_application, helm, newest_version).await?;
 
-    let apps = list_applications(&client).await?;
-    for a in apps {
-        if !a.contains_helm() {
-            continue;
+    info!("successfully update the application spec");
+
+    let sync_timeout = match update_options.sync_timeout {
+        Some(sync_timeout) => sync_timeout,
+        None => return Ok(None),
+    };
+
+    let report = sync_update(
+        client,
+        argo_application,
+        helm,
+        newest_version,
+        sync_timeout,
+        update_options.rollback_window,
+    )
+    .await?;
+
+    Ok(Some(report))
+}
+
+fn log_sync_outcome(
+    argo_application: &Application,
+    newest_version: &str,
+    sync_timeout: Duration,
+    outcome: &SyncOutcome,
+) {
+    match outcome {
+        SyncOutcome::Succeeded => info!(
+            "app: {} | sync to {} succeeded",
+            argo_application.name_any(),
+            newest_version
+        ),
+        SyncOutcome::Failed(message) => error!(
+            "app: {} | sync to {} failed: {}",
+            argo_application.name_any(),
+            newest_version,
+            message
+        ),
+        SyncOutcome::TimedOut => warn!(
+            "app: {} | sync to {} did not finish within {}s",
+            argo_application.name_any(),
+            newest_version,
+            sync_timeout.as_secs()
+        ),
+    }
+}
+
+async fn sync_update(
+    client: &Client,
+    argo_application: &Application,
+    helm: &HelmChart,
+    newest_version: &str,
+    sync_timeout: Duration,
+    rollback_window: Option<Duration>,
+) -> anyhow::Result<SyncReport> {
+    let outcome =
+        sync_application(client, argo_application, helm, newest_version, sync_timeout).await?;
+
+    log_sync_outcome(argo_application, newest_version, sync_timeout, &outcome);
+
+    let mut report = SyncReport {
+        outcome: outcome.clone(),
+        rollback: None,
+    };
+
+    let rollback_window = match rollback_window {
+        Some(window) => window,
+        None => return Ok(report),
+    };
+
+    let degraded = match outcome {
+        SyncOutcome::Succeeded => {
+            let health = wait_for_degraded(client, argo_application, rollback_window).await?;
+
+            if let Some(health) = &health {
+                error!(
+                    "app: {} | became {} after the update: {}",
+                    argo_application.name_any(),
+                    health.status.as_deref().unwrap_or_default(),
+                    health.message.as_deref().unwrap_or_default(),
+                );
+            }
+
+            health.is_some()
         }
+        SyncOutcome::Failed(_) | SyncOutcome::TimedOut => false,
+    };
 
-        if a.helm_in_source() {
-            let result =
-                verify_helm_source(&client, &a, &(a.clone()).spec.source.unwrap(), args.update)
-                    .await;
+    if outcome == SyncOutcome::TimedOut {
+        warn!(
+            "app: {} | not rolling back, the sync to {} may still be running",
+            argo_application.name_any(),
+            newest_version,
+        );
+    }
+
+    if !outcome.needs_rollback(degraded) {
+        return Ok(report);
+    }
+
+    patch_application(client, argo_application, helm, &helm.revision).await?;
+    let rollback =
+        sync_application(client, argo_application, helm, &helm.revision, sync_timeout).await?;
+
+    warn!(
+        "app: {} | rolled back from {} to {} (sync {})",
+        argo_application.name_any(),
+        newest_version,
+        helm.revision,
+        rollback,
+    );
+
+    report.rollback = Some((helm.revision.clone(), rollback));
+
+    Ok(report)
+}
+
+async fn check_application(
+    client: &Client,
+    helm_client: &dyn HelmRepoClient,
+    application: &Application,
+    update_options: Option<&UpdateOptions>,
+    write_back: bool,
+) -> Report {
+    let mut report = Report::default();
+
+    if !application.contains_helm() {
+        return report;
+    }
+
+    for (index, source) in application.spec.helm_sources() {
+        report
+            .sources
+            .extend(ScannedSource::new(application, &source));
+        report
+            .findings
+            .extend(verify_deployed_revision(application, &source, index));
 
-            if result.is_err() {
+        let result =
+            verify_helm_source(client, helm_client, application, &source, update_options).await;
+
+        match result {
+            Ok(finding) => report.findings.extend(finding),
+            Err(e) => {
                 error!(
-                    "cannot fetch update for application '{}': {:?}",
-                    a.name_any(),
-                    result.err().unwrap()
+                    "cannot fetch update for application '{}': {:#}",
+                    application.name_any(),
+                    e
                 );
+
+                report.errors.push(ScanError::new(application, &source, &e));
+            }
+        }
+    }
+
+    if write_back && report.errors.is_empty() {
+        let charts: Vec<OutdatedChart> = report
+            .updates()
+            .map(|f| OutdatedChart {
+                chart: f.chart.clone(),
+                current: f.current.clone(),
+                latest: f.update().unwrap().version.clone(),
+            })
+            .collect();
+
+        if let Err(e) = write_back_findings(client, application, &charts).await {
+            warn!(
+                "cannot write findings back to application '{}': {:?}",
+                application.name_any(),
+                e
+            );
+        }
+    }
+
+    report
+}
+
+async fn scan_cluster(
+    args: &Args,
+    client: &Client,
+    helm_client: &CachedHelmRepoClient<HelmRepoReqwestClient>,
+) -> anyhow::Result<Report> {
+    let update_options = UpdateOptions::from_args(args);
+
+    let mut report = Report::default();
+
+    let apps = list_applications(client).await?;
+    let app_sets = list_application_sets(client).await;
+
+    let sources = apps.iter().map(|a| a.spec.helm_sources()).chain(
+        app_sets
+            .iter()
+            .flatten()
+            .map(|a| a.spec.template.spec.helm_sources()),
+    );
+    for (_, source) in sources.flatten() {
+        helm_client.expect_source(&source);
+    }
+
+    for a in apps {
+        let app_report = check_application(
+            client,
+            helm_client,
+            &a,
+            update_options.as_ref(),
+            args.write_back,
+        )
+        .await;
+
+        report.merge(app_report);
+    }
+
+    match app_sets {
+        Ok(app_sets) => {
+            for app_set in app_sets {
+                for (_, source) in app_set.spec.template.spec.helm_sources() {
+                    report.sources.extend(ScannedSource::new(&app_set, &source));
+
+                    let result = verify_application_set_source(
+                        client,
+                        helm_client,
+                        &app_set,
+                        &source,
+                        update_options.as_ref(),
+                    )
+                    .await;
+
+                    match result {
+                        Ok(finding) => report.findings.extend(finding),
+                        Err(e) => {
+                            error!(
+                                "cannot fetch update for application set '{}': {:#}",
+                                app_set.name_any(),
+                                e
+                            );
+
+                            report.errors.push(ScanError::new(&app_set, &source, &e));
+                        }
+                    }
+                }
             }
         }
+        Err(e) => warn!("cannot list application sets: {:?}", e),
+    }
+
+    if args.publish_report {
+        publish_report(client, &report).await;
+    }
+
+    Ok(report)
+}
+
+async fn publish_report(client: &Client, report: &Report) {
+    let generated_at = Utc::now();
+    let mut namespaces = report.by_namespace();
+    namespaces
+        .entry(client.default_namespace().to_string())
+        .or_default();
+
+    for (namespace, namespace_report) in namespaces {
+        let spec = namespace_report.to_report_spec(generated_at);
+
+        match apply_chart_update_report(client, &namespace, spec).await {
+            Ok(()) => info!("published chart update report to namespace {}", namespace),
+            Err(e) => error!(
+                "cannot publish chart update report to namespace {}: {:?}",
+                namespace, e
+            ),
+        }
+    }
+}
+
+async fn scan_manifests(
+    dir: &Path,
+    args: &Args,
+    helm_client: &CachedHelmRepoClient<HelmRepoReqwestClient>,
+) -> anyhow::Result<Report> {
+    let update_options = UpdateOptions::from_args(args);
+    let mut report = Report::default();
+
+    let manifests = scan_directory(dir)?;
+
+    for manifest in &manifests.applications {
+        for (_, source) in manifest.application.spec.helm_sources() {
+            helm_client.expect_source(&source);
+        }
+    }
+    for manifest_chart in &manifests.charts {
+        helm_client.expect_chart(&manifest_chart.helm.repo, &manifest_chart.helm.chart);
+    }
+
+    let checkouts = Checkouts {
+        default: dir.to_path_buf(),
+        repos: args.checkout.clone(),
+    };
+
+    for manifest in manifests.applications {
+        for (index, source) in manifest.application.spec.helm_sources() {
+            let values = resolve_values(&manifest.application.spec, &source, &checkouts);
+
+            for missing in &values.missing {
+                warn!(
+                    "app: {} | value file {} cannot be found | {}",
+                    manifest.application.name_any(),
+                    missing,
+                    manifest.location,
+                );
+            }
 
-        if a.helm_in_sources() {
-            for source in (a.clone()).spec.sources.unwrap() {
-                let result = verify_helm_source(&client, &a, &source, args.update).await;
+            debug!(
+                "app: {} | resolved values from {:?}",
+                manifest.application.name_any(),
+                values.files
+            );
 
-                if result.is_err() {
+            report
+                .sources
+                .extend(ScannedSource::new(&manifest.application, &source).map(|s| {
+                    s.with_location(
+                        manifest
+                            .location
+                            .with_yaml_path(target_revision_path(index)),
+                    )
+                }));
+
+            let result =
+                verify_manifest_source(helm_client, &manifest, &source, index, &values.values)
+                    .await;
+
+            match result {
+                Ok(finding) => report.findings.extend(finding),
+                Err(e) => {
                     error!(
-                        "cannot fetch update for application '{}': {:?}",
-                        a.name_any(),
-                        result.err().unwrap()
+                        "cannot fetch update for application '{}' in {}: {:#}",
+                        manifest.application.name_any(),
+                        manifest.location,
+                        e
+                    );
+
+                    report.errors.push(
+                        ScanError::new(&manifest.application, &source, &e).with_location(
+                            manifest
+                                .location
+                                .with_yaml_path(target_revision_path(index)),
+                        ),
                     );
                 }
             }
         }
     }
 
+    for manifest_chart in manifests.charts {
+        report
+            .sources
+            .push(ScannedSource::from_manifest_chart(&manifest_chart));
+
+        let result = verify_manifest_chart(helm_client, &manifest_chart).await;
+
+        match result {
+            Ok(finding) => report.findings.extend(finding),
+            Err(e) => {
+                error!(
+                    "cannot fetch update for dependency '{}' of chart '{}' in {}: {:#}",
+                    manifest_chart.helm.chart, manifest_chart.name, manifest_chart.location, e
+                );
+
+                report
+                    .errors
+                    .push(ScanError::from_manifest_chart(&manifest_chart, &e));
+            }
+        }
+    }
+
+    if let Some(update_options) = update_options {
+        update_manifests(dir, &report.findings, &update_options)?;
+    }
+
+    Ok(report)
+}
+
+fn notifier(args: &Args) -> anyhow::Result<Notifier> {
+    Notifier::new(
+        args.webhook.clone(),
+        args.notify_state.clone(),
+        Duration::from_secs(args.connect_timeout),
+        Duration::from_secs(args.read_timeout),
+    )
+}
+
+async fn scan(args: &Args) -> anyhow::Result<Report> {
+    let report = match &args.from_dir {
+        Some(dir) => {
+            let helm_client =
+                CachedHelmRepoClient::new(helm_repo_client(args, None).await?, Duration::MAX)
+                    .with_failure_ttl(Duration::MAX);
+
+            scan_manifests(dir, args, &helm_client).await?
+        }
+        None => {
+            let client = init_client(args.context.clone(), args.namespace.clone()).await?;
+            let helm_client = CachedHelmRepoClient::new(
+                helm_repo_client(args, Some(&client)).await?,
+                Duration::MAX,
+            )
+            .with_failure_ttl(Duration::MAX);
+
+            scan_cluster(args, &client, &helm_client).await?
+        }
+    };
+
+    if let Err(e) = notifier(args)?.notify(&report).await {
+        error!("cannot send notifications: {:?}", e);
+    }
+
+    Ok(report)
+}
+
+fn update_manifests(
+    dir: &Path,
+    findings: &[Finding],
+    update_options: &UpdateOptions,
+) -> anyhow::Result<()> {
+    let mut accepted = Vec::new();
+
+    for finding in findings {
+        let (location, update) = match (&finding.location, finding.update()) {
+            (Some(location), Some(update)) => (location, update),
+            _ => continue,
+        };
+
+        if !confirm_update(
+            &finding.application,
+            &finding.current,
+            &update.version,
+            update_options,
+        )? {
+            continue;
+        }
+
+        if update_options.git.is_none() {
+            update_manifest(location, &update.version, update_options.refresh_chart_lock)?;
+
+            info!("successfully update {}", location.path.display());
+        }
+
+        accepted.push(finding.clone());
+    }
+
+    if let Some(git_options) = &update_options.git {
+        commit_updates(
+            dir,
+            &accepted,
+            git_options,
+            update_options.refresh_chart_lock,
+        )?;
+    }
+
     Ok(())
 }
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let args = Args::parse();
+    env_logger::init_from_env(
+        env_logger::Env::default().filter_or(env_logger::DEFAULT_FILTER_ENV, "info"),
+    );
+
+    match run(args).await {
+        Ok(code) => code,
+        Err(e) => {
+            error!("{:?}", e);
+            ExitCode::from(EXIT_SCAN_FAILED)
+        }
+    }
+}
+
+async fn run(args: Args) -> anyhow::Result<ExitCode> {
+    match args.command.clone() {
+        Some(Command::Serve(serve_args)) => {
+            serve(args, serve_args).await?;
+            return Ok(ExitCode::SUCCESS);
+        }
+        Some(Command::Watch(watch_args)) => {
+            watch(args, watch_args).await?;
+            return Ok(ExitCode::SUCCESS);
+        }
+        Some(Command::Crd) => {
+            print!("{}", serde_yaml::to_string(&ChartUpdateReport::crd())?);
+            return Ok(ExitCode::SUCCESS);
+        }
+        None => {}
+    }
+
+    let report = scan(&args).await?;
+    let color = std::io::stdout().is_terminal() && std::env::var_os("NO_COLOR").is_none();
+
+    match args.output {
+        OutputFormat::Table => print!(
+            "{}",
+            render_table(&report, args.group_by, color, Utc::now())
+        ),
+        OutputFormat::Markdown => print!("{}", render_markdown(&report)),
+        OutputFormat::Sarif => print!("{}", render_sarif(&report, args.from_dir.as_deref())),
+        OutputFormat::Junit => print!(
+            "{}",
+            render_junit(&report, args.fail_on.unwrap_or(FailOn::Any))
+        ),
+    }
+
+    info!(
+        "found {} outdated chart(s) and {} application source(s) not running their target revision",
+        report.updates().count(),
+        report.drifts().count(),
+    );
+
+    Ok(ExitCode::from(report.exit_code(args.fail_on)))
+}
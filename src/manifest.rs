@@ -0,0 +1,552 @@
+use std::{
+    fmt::Display,
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use anyhow::bail;
+use log::{debug, info, warn};
+use serde::Deserialize;
+use serde_yaml::Value;
+
+use crate::{
+    helm::{parse_semver, HelmChart},
+    kubernetes::Application,
+    yaml::{find_scalar, replace_scalar, PathSegment},
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ManifestLocation {
+    pub path: PathBuf,
+    pub document: usize,
+    pub yaml_path: Vec<PathSegment>,
+}
+
+impl ManifestLocation {
+    pub fn with_yaml_path(&self, yaml_path: Vec<PathSegment>) -> Self {
+        Self {
+            yaml_path,
+            ..self.clone()
+        }
+    }
+
+    pub fn line_column(&self) -> Option<(usize, usize)> {
+        let content = fs::read_to_string(&self.path).ok()?;
+        let span = find_scalar(&content, self.document, &self.yaml_path)?;
+
+        Some((span.line + 1, span.column + 1))
+    }
+}
+
+impl Display for ManifestLocation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}#{}", self.path.display(), self.document)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ManifestApplication {
+    pub location: ManifestLocation,
+    pub application: Application,
+}
+
+#[derive(Debug)]
+pub struct ManifestChart {
+    pub kind: String,
+    pub name: String,
+    pub location: ManifestLocation,
+    pub helm: HelmChart,
+}
+
+#[derive(Debug, Default)]
+pub struct Manifests {
+    pub applications: Vec<ManifestApplication>,
+    pub charts: Vec<ManifestChart>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ChartFile {
+    name: String,
+    dependencies: Option<Vec<ChartDependency>>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ChartDependency {
+    name: String,
+    version: Option<String>,
+    repository: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct KustomizationFile {
+    #[serde(rename = "helmCharts")]
+    helm_charts: Option<Vec<KustomizeHelmChart>>,
+}
+
+#[derive(Deserialize, Debug)]
+struct KustomizeHelmChart {
+    name: String,
+    repo: Option<String>,
+    version: Option<String>,
+}
+
+pub fn find_yaml_files(dir: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let mut output = Vec::new();
+
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+
+        let is_hidden = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .is_some_and(|n| n.starts_with('.'));
+
+        if is_hidden {
+            continue;
+        }
+
+        if path.is_dir() {
+            output.extend(find_yaml_files(&path)?);
+            continue;
+        }
+
+        let is_yaml = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .is_some_and(|e| e == "yaml" || e == "yml")
+            || is_kustomization_file(&path);
+
+        if is_yaml {
+            output.push(path);
+        }
+    }
+
+    output.sort();
+
+    Ok(output)
+}
+
+pub fn parse_applications(path: &Path, content: &str) -> Vec<ManifestApplication> {
+    let mut output = Vec::new();
+
+    for (document, deserializer) in serde_yaml::Deserializer::from_str(content).enumerate() {
+        let value = match Value::deserialize(deserializer) {
+            Ok(value) => value,
+            Err(e) => {
+                debug!("cannot parse {}: {:?}", path.display(), e);
+                break;
+            }
+        };
+
+        let is_application = value.get("apiVersion").and_then(Value::as_str)
+            == Some("argoproj.io/v1alpha1")
+            && value.get("kind").and_then(Value::as_str) == Some("Application");
+
+        if !is_application {
+            continue;
+        }
+
+        let location = ManifestLocation {
+            path: path.to_path_buf(),
+            document,
+            yaml_path: Vec::new(),
+        };
+
+        match serde_yaml::from_value::<Application>(value) {
+            Ok(application) => output.push(ManifestApplication {
+                location,
+                application,
+            }),
+            Err(e) => warn!("cannot deserialize application in {}: {}", location, e),
+        }
+    }
+
+    output
+}
+
+pub fn target_revision_path(source_index: Option<usize>) -> Vec<PathSegment> {
+    let mut output = vec![PathSegment::Key("spec".to_string())];
+
+    match source_index {
+        Some(index) => {
+            output.push(PathSegment::Key("sources".to_string()));
+            output.push(PathSegment::Index(index));
+        }
+        None => output.push(PathSegment::Key("source".to_string())),
+    }
+
+    output.push(PathSegment::Key("targetRevision".to_string()));
+
+    output
+}
+
+pub fn parse_chart_dependencies(path: &Path, content: &str) -> Vec<ManifestChart> {
+    let chart: ChartFile = match serde_yaml::from_str(content) {
+        Ok(chart) => chart,
+        Err(e) => {
+            warn!("cannot parse chart {}: {}", path.display(), e);
+
+            return Vec::new();
+        }
+    };
+
+    let mut output = Vec::new();
+
+    for (index, dependency) in chart
+        .dependencies
+        .unwrap_or_default()
+        .into_iter()
+        .enumerate()
+    {
+        let (version, repository) = match (dependency.version, dependency.repository) {
+            (Some(version), Some(repository)) => (version, repository),
+            _ => continue,
+        };
+
+        if !repository.starts_with("http://") && !repository.starts_with("https://") {
+            debug!(
+                "skipping dependency {} of {} from {}",
+                dependency.name, chart.name, repository
+            );
+            continue;
+        }
+
+        if parse_semver(&version).is_none() {
+            debug!(
+                "skipping dependency {} of {} with version range {}",
+                dependency.name, chart.name, version
+            );
+            continue;
+        }
+
+        output.push(ManifestChart {
+            kind: "Chart".to_string(),
+            name: chart.name.clone(),
+            location: ManifestLocation {
+                path: path.to_path_buf(),
+                document: 0,
+                yaml_path: vec![
+                    PathSegment::Key("dependencies".to_string()),
+                    PathSegment::Index(index),
+                    PathSegment::Key("version".to_string()),
+                ],
+            },
+            helm: HelmChart {
+                chart: dependency.name,
+                repo: repository,
+                revision: version,
+            },
+        });
+    }
+
+    output
+}
+
+pub fn parse_kustomize_helm_charts(path: &Path, content: &str) -> Vec<ManifestChart> {
+    let kustomization: KustomizationFile = match serde_yaml::from_str(content) {
+        Ok(kustomization) => kustomization,
+        Err(e) => {
+            warn!("cannot parse kustomization {}: {}", path.display(), e);
+
+            return Vec::new();
+        }
+    };
+
+    let name = path
+        .parent()
+        .and_then(|p| p.file_name())
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    let mut output = Vec::new();
+
+    for (index, helm_chart) in kustomization
+        .helm_charts
+        .unwrap_or_default()
+        .into_iter()
+        .enumerate()
+    {
+        let (version, repo) = match (helm_chart.version, helm_chart.repo) {
+            (Some(version), Some(repo)) => (version, repo),
+            _ => continue,
+        };
+
+        if !repo.starts_with("http://") && !repo.starts_with("https://") {
+            debug!(
+                "skipping helm chart {} of kustomization {} from {}",
+                helm_chart.name, name, repo
+            );
+            continue;
+        }
+
+        output.push(ManifestChart {
+            kind: "Kustomization".to_string(),
+            name: name.clone(),
+            location: ManifestLocation {
+                path: path.to_path_buf(),
+                document: 0,
+                yaml_path: vec![
+                    PathSegment::Key("helmCharts".to_string()),
+                    PathSegment::Index(index),
+                    PathSegment::Key("version".to_string()),
+                ],
+            },
+            helm: HelmChart {
+                chart: helm_chart.name,
+                repo,
+                revision: version,
+            },
+        });
+    }
+
+    output
+}
+
+fn is_kustomization_file(path: &Path) -> bool {
+    matches!(
+        path.file_name().and_then(|n| n.to_str()),
+        Some("kustomization.yaml" | "kustomization.yml" | "Kustomization")
+    )
+}
+
+fn is_chart_file(path: &Path) -> bool {
+    path.file_name().and_then(|n| n.to_str()) == Some("Chart.yaml")
+}
+
+fn is_subchart(dir: &Path, path: &Path) -> bool {
+    // dependencies vendored by `helm dependency update` live in the charts/ directory of their parent
+    path.ancestors()
+        .skip(1)
+        .take_while(|ancestor| ancestor.starts_with(dir) && *ancestor != dir)
+        .any(|ancestor| {
+            ancestor.file_name().and_then(|n| n.to_str()) == Some("charts")
+                && ancestor
+                    .parent()
+                    .is_some_and(|parent| parent.join("Chart.yaml").is_file())
+        })
+}
+
+pub fn refresh_chart_lock(chart_dir: &Path) -> anyhow::Result<()> {
+    let output = Command::new("helm")
+        .args(["dependency", "update"])
+        .arg(chart_dir)
+        .output()?;
+
+    if !output.status.success() {
+        bail!(
+            "cannot refresh Chart.lock of {}: {}",
+            chart_dir.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    info!("refreshed Chart.lock of {}", chart_dir.display());
+
+    Ok(())
+}
+
+pub fn update_manifest(
+    location: &ManifestLocation,
+    value: &str,
+    refresh_lock: bool,
+) -> anyhow::Result<Vec<PathBuf>> {
+    let content = fs::read_to_string(&location.path)?;
+    let content = replace_scalar(&content, location.document, &location.yaml_path, value)?;
+
+    fs::write(&location.path, content)?;
+
+    let mut changed_files = vec![location.path.clone()];
+
+    if refresh_lock && is_chart_file(&location.path) {
+        let chart_dir = location.path.parent().unwrap_or(Path::new("."));
+
+        refresh_chart_lock(chart_dir)?;
+        changed_files.push(chart_dir.join("Chart.lock"));
+    }
+
+    Ok(changed_files)
+}
+
+pub fn scan_directory(dir: &Path) -> anyhow::Result<Manifests> {
+    let mut output = Manifests::default();
+
+    for path in find_yaml_files(dir)? {
+        let content = fs::read_to_string(&path)?;
+
+        if is_chart_file(&path) {
+            if is_subchart(dir, &path) {
+                debug!("skipping subchart {}", path.display());
+                continue;
+            }
+
+            output
+                .charts
+                .extend(parse_chart_dependencies(&path, &content));
+            continue;
+        }
+
+        if is_kustomization_file(&path) {
+            output
+                .charts
+                .extend(parse_kustomize_helm_charts(&path, &content));
+            continue;
+        }
+
+        output
+            .applications
+            .extend(parse_applications(&path, &content));
+    }
+
+    Ok(output)
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use kube::ResourceExt;
+
+    use super::{
+        parse_applications, parse_chart_dependencies, parse_kustomize_helm_charts, scan_directory,
+    };
+
+    #[test]
+    fn parse_applications_multi_document() {
+        let content = "---
+apiVersion: v1
+kind: Namespace
+metadata:
+  name: monitoring
+---
+apiVersion: argoproj.io/v1alpha1
+kind: Application
+metadata:
+  name: prometheus
+  namespace: argocd
+spec:
+  project: default
+  source:
+    chart: kube-prometheus-stack
+    repoURL: https://prometheus-community.github.io/helm-charts
+    targetRevision: 45.0.0
+---
+apiVersion: argoproj.io/v1alpha1
+kind: Application
+metadata:
+  name: loki
+spec:
+  project: default
+  sources:
+    - chart: loki
+      repoURL: https://grafana.github.io/helm-charts
+      targetRevision: 5.0.0
+";
+
+        let result = parse_applications(Path::new("apps.yaml"), content);
+
+        assert_eq!(2, result.len());
+        assert_eq!("prometheus", result[0].application.name_any());
+        assert_eq!("apps.yaml#1", result[0].location.to_string());
+        assert_eq!("loki", result[1].application.name_any());
+        assert_eq!(2, result[1].location.document);
+    }
+
+    #[test]
+    fn parse_applications_skips_invalid_yaml() {
+        let content = "apiVersion: v1
+kind: ConfigMap
+data:
+  key: {{ .Values.key }}
+";
+
+        let result = parse_applications(Path::new("template.yaml"), content);
+
+        assert!(result.is_empty());
+    }
+
+    #[test]
+    fn parse_chart_dependencies_with_repository() {
+        let content = "apiVersion: v2
+name: platform
+version: 1.0.0
+dependencies:
+  - name: common
+    version: 2.x.x
+    repository: https://charts.bitnami.com/bitnami
+  - name: local
+    version: 0.1.0
+    repository: file://../local
+  - name: redis
+    version: 17.0.0 # pinned
+    repository: https://charts.bitnami.com/bitnami
+    condition: redis.enabled
+";
+
+        let result = parse_chart_dependencies(Path::new("platform/Chart.yaml"), content);
+
+        assert_eq!(1, result.len());
+        assert_eq!("platform", result[0].name);
+        assert_eq!("redis", result[0].helm.chart);
+        assert_eq!("17.0.0", result[0].helm.revision);
+        assert_eq!(
+            Some(2),
+            result[0].location.yaml_path.iter().find_map(|p| match p {
+                crate::yaml::PathSegment::Index(i) => Some(*i),
+                _ => None,
+            })
+        );
+    }
+
+    #[test]
+    fn parse_kustomize_helm_charts_with_repo() {
+        let content = "apiVersion: kustomize.config.k8s.io/v1beta1
+kind: Kustomization
+resources:
+  - namespace.yaml
+helmCharts:
+- name: ingress-nginx
+  repo: https://kubernetes.github.io/ingress-nginx
+  version: 4.7.0
+  releaseName: ingress
+- name: local
+  repo: oci://registry.example.com/charts
+  version: 1.0.0
+";
+
+        let result =
+            parse_kustomize_helm_charts(Path::new("overlays/ingress/kustomization.yaml"), content);
+
+        assert_eq!(1, result.len());
+        assert_eq!("Kustomization", result[0].kind);
+        assert_eq!("ingress", result[0].name);
+        assert_eq!("ingress-nginx", result[0].helm.chart);
+        assert_eq!("4.7.0", result[0].helm.revision);
+    }
+
+    #[test]
+    fn scan_directory_skips_vendored_subcharts() {
+        let dir = tempfile::tempdir().expect("cannot create temp dir");
+        let chart = "apiVersion: v2
+name: {name}
+version: 1.0.0
+dependencies:
+- name: redis
+  repository: https://charts.bitnami.com/bitnami
+  version: 17.0.0
+";
+
+        for chart_dir in ["charts/platform", "charts/platform/charts/backend"] {
+            let chart_dir = dir.path().join(chart_dir);
+            let name = chart_dir.file_name().unwrap().to_str().unwrap();
+
+            std::fs::create_dir_all(&chart_dir).unwrap();
+            std::fs::write(chart_dir.join("Chart.yaml"), chart.replace("{name}", name)).unwrap();
+        }
+
+        let result = scan_directory(dir.path()).expect("cannot scan directory");
+
+        assert_eq!(1, result.charts.len());
+        assert_eq!("platform", result.charts[0].name);
+    }
+}
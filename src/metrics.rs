@@ -0,0 +1,242 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt::Write,
+    time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+
+use crate::{
+    helm::without_query,
+    report::{FindingsStore, Report, ScanError},
+};
+
+#[derive(Debug, Clone, Default)]
+pub struct Metrics {
+    pub findings: FindingsStore,
+    pub fetch_errors: BTreeMap<(String, String), u64>,
+    pub scan_duration: Option<Duration>,
+    pub last_success: Option<DateTime<Utc>>,
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn labels(pairs: &[(&str, &str)]) -> String {
+    pairs
+        .iter()
+        .map(|(name, value)| format!("{}=\"{}\"", name, escape_label(value)))
+        .collect::<Vec<String>>()
+        .join(",")
+}
+
+impl Metrics {
+    pub fn is_ready(&self) -> bool {
+        self.last_success.is_some()
+    }
+
+    fn count_errors<'a>(&mut self, errors: impl Iterator<Item = &'a ScanError>) {
+        for error in errors {
+            *self
+                .fetch_errors
+                .entry((without_query(&error.repo), error.reason().to_string()))
+                .or_default() += 1;
+        }
+    }
+
+    pub fn record_scan(&mut self, report: Report, duration: Duration) {
+        self.count_errors(report.errors.iter());
+
+        self.findings = FindingsStore::from(report);
+        self.scan_duration = Some(duration);
+        self.last_success = Some(Utc::now());
+    }
+
+    // errors of an index served from the cache were already counted when it was fetched
+    pub fn record_application(&mut self, key: String, report: Report, fetched: &HashSet<String>) {
+        self.count_errors(report.errors.iter().filter(|e| fetched.contains(&e.repo)));
+
+        self.findings.insert(key, report);
+
+        if self.last_success.is_some() {
+            self.last_success = Some(Utc::now());
+        }
+    }
+
+    pub fn remove_application(&mut self, key: &str) {
+        self.findings.remove(key);
+    }
+
+    pub fn record_sync(&mut self, duration: Duration) {
+        self.scan_duration = Some(duration);
+        self.last_success = Some(Utc::now());
+    }
+
+    pub fn record_failure(&mut self, duration: Duration) {
+        self.scan_duration = Some(duration);
+    }
+
+    pub fn render(&self) -> String {
+        let report = self.findings.report();
+        let mut output = String::new();
+
+        output.push_str(
+            "# HELP argo_helm_chart_outdated Helm chart with a newer version available\n",
+        );
+        output.push_str("# TYPE argo_helm_chart_outdated gauge\n");
+
+        for finding in report.updates() {
+            let latest = finding
+                .update()
+                .map(|u| u.version.as_str())
+                .unwrap_or_default();
+            let kind = finding
+                .update_kind()
+                .map(|k| k.to_string())
+                .unwrap_or_default();
+
+            let _ = writeln!(
+                output,
+                "argo_helm_chart_outdated{{{}}} 1",
+                labels(&[
+                    ("app", &finding.application),
+                    ("namespace", &finding.namespace),
+                    ("chart", &finding.chart),
+                    ("current", &finding.current),
+                    ("latest", latest),
+                    ("kind", &kind),
+                ])
+            );
+        }
+
+        output.push_str(
+            "# HELP argo_helm_repo_fetch_errors_total Failed chart lookups per helm repository\n",
+        );
+        output.push_str("# TYPE argo_helm_repo_fetch_errors_total counter\n");
+
+        for ((repo, reason), count) in &self.fetch_errors {
+            let _ = writeln!(
+                output,
+                "argo_helm_repo_fetch_errors_total{{{}}} {}",
+                labels(&[("repo", repo), ("reason", reason)]),
+                count
+            );
+        }
+
+        if let Some(duration) = self.scan_duration {
+            output.push_str("# HELP argo_helm_scan_duration_seconds Duration of the last scan\n");
+            output.push_str("# TYPE argo_helm_scan_duration_seconds gauge\n");
+            let _ = writeln!(
+                output,
+                "argo_helm_scan_duration_seconds {}",
+                duration.as_secs_f64()
+            );
+        }
+
+        if let Some(last_success) = self.last_success {
+            output.push_str("# HELP argo_helm_last_success_timestamp_seconds Unix timestamp of the last successful scan\n");
+            output.push_str("# TYPE argo_helm_last_success_timestamp_seconds gauge\n");
+            let _ = writeln!(
+                output,
+                "argo_helm_last_success_timestamp_seconds {}",
+                last_success.timestamp()
+            );
+        }
+
+        output
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{collections::HashSet, time::Duration};
+
+    use crate::{
+        helm::ChartUpdate,
+        report::{Finding, FindingKind, Report, ScanError},
+    };
+
+    use super::Metrics;
+
+    #[test]
+    fn render_findings_and_errors() {
+        let report = Report {
+            findings: vec![Finding {
+                resource_kind: "Application".to_owned(),
+                namespace: "argocd".to_owned(),
+                application: "cert-manager".to_owned(),
+                chart: "cert-manager".to_owned(),
+                repo: "https://charts.jetstack.io".to_owned(),
+                current: "v1.12.0".to_owned(),
+                kind: FindingKind::UpdateAvailable(ChartUpdate {
+                    version: "v1.13.1".to_owned(),
+                    changelog: Vec::new(),
+                    ..Default::default()
+                }),
+                owner: None,
+                location: None,
+            }],
+            errors: vec![ScanError {
+                resource_kind: "Application".to_owned(),
+                namespace: "argocd".to_owned(),
+                application: "loki".to_owned(),
+                chart: "loki".to_owned(),
+                repo: "https://grafana.github.io/helm-charts?token=secret".to_owned(),
+                message: "connection refused".to_owned(),
+                location: None,
+                cause: None,
+            }],
+            sources: Vec::new(),
+        };
+
+        let mut metrics = Metrics::default();
+        assert!(!metrics.is_ready());
+
+        metrics.record_scan(report.clone(), Duration::from_millis(1500));
+        metrics.record_scan(report, Duration::from_millis(1500));
+
+        let output = metrics.render();
+
+        assert!(metrics.is_ready());
+        assert!(output.contains(
+            "argo_helm_chart_outdated{app=\"cert-manager\",namespace=\"argocd\",chart=\"cert-manager\",current=\"v1.12.0\",latest=\"v1.13.1\",kind=\"minor\"} 1\n"
+        ));
+        assert!(output.contains(
+            "argo_helm_repo_fetch_errors_total{repo=\"https://grafana.github.io/helm-charts\",reason=\"unknown\"} 2\n"
+        ));
+        assert!(output.contains("argo_helm_scan_duration_seconds 1.5\n"));
+        assert!(!output.contains("secret"));
+    }
+
+    #[test]
+    fn record_application_counts_fetched_errors() {
+        let report = Report {
+            findings: Vec::new(),
+            errors: vec![ScanError {
+                resource_kind: "Application".to_owned(),
+                namespace: "argocd".to_owned(),
+                application: "loki".to_owned(),
+                chart: "loki".to_owned(),
+                repo: "https://grafana.github.io/helm-charts".to_owned(),
+                message: "connection refused".to_owned(),
+                location: None,
+                cause: None,
+            }],
+            sources: Vec::new(),
+        };
+
+        let mut metrics = Metrics::default();
+        let fetched = HashSet::from(["https://grafana.github.io/helm-charts".to_owned()]);
+
+        metrics.record_application("argocd/loki".to_owned(), report.clone(), &fetched);
+        metrics.record_application("argocd/loki".to_owned(), report, &HashSet::new());
+
+        assert!(metrics.render().contains(
+            "argo_helm_repo_fetch_errors_total{repo=\"https://grafana.github.io/helm-charts\",reason=\"unknown\"} 1\n"
+        ));
+    }
+}
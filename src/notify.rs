@@ -0,0 +1,480 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
+
+use anyhow::{anyhow, bail};
+use log::{debug, info};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::report::Report;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WebhookFormat {
+    Slack,
+    Teams,
+    Json,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Webhook {
+    pub format: WebhookFormat,
+    pub url: String,
+}
+
+impl FromStr for Webhook {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (format, url) = match s.split_once('=') {
+            Some((format, url)) if !url.is_empty() => (format, url),
+            _ => bail!("expected <slack|teams|json>=<url>, got '{}'", s),
+        };
+
+        let format = match format {
+            "slack" => WebhookFormat::Slack,
+            "teams" => WebhookFormat::Teams,
+            "json" => WebhookFormat::Json,
+            _ => bail!("unknown webhook format '{}'", format),
+        };
+
+        Ok(Self {
+            format,
+            url: url.to_string(),
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct NotifiedFinding {
+    pub namespace: String,
+    pub application: String,
+    pub chart: String,
+    pub current: String,
+    pub latest: String,
+}
+
+impl NotifiedFinding {
+    fn key(&self) -> String {
+        format!("{}/{}/{}", self.namespace, self.application, self.chart)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct NotifyState {
+    pub notified: BTreeMap<String, NotifiedFinding>,
+}
+
+impl NotifyState {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct Digest {
+    pub new: Vec<NotifiedFinding>,
+    pub resolved: Vec<NotifiedFinding>,
+}
+
+impl Digest {
+    pub fn is_empty(&self) -> bool {
+        self.new.is_empty() && self.resolved.is_empty()
+    }
+
+    fn text(&self) -> String {
+        let mut lines = Vec::new();
+
+        if !self.new.is_empty() {
+            lines.push(format!(
+                "{} helm chart update(s) available:",
+                self.new.len()
+            ));
+            lines.extend(self.new.iter().map(|f| {
+                format!(
+                    "- {}/{}: {} {} -> {}",
+                    f.namespace, f.application, f.chart, f.current, f.latest
+                )
+            }));
+        }
+
+        if !self.resolved.is_empty() {
+            lines.push(format!("{} helm chart(s) caught up:", self.resolved.len()));
+            lines.extend(self.resolved.iter().map(|f| {
+                format!(
+                    "- {}/{}: {} is no longer behind {}",
+                    f.namespace, f.application, f.chart, f.latest
+                )
+            }));
+        }
+
+        lines.join("\n")
+    }
+
+    pub fn payload(&self, format: WebhookFormat) -> Value {
+        match format {
+            WebhookFormat::Slack => json!({ "text": self.text() }),
+            WebhookFormat::Teams => json!({
+                "@type": "MessageCard",
+                "@context": "https://schema.org/extensions",
+                "summary": "argo-helm-updater",
+                "title": "argo-helm-updater",
+                "text": self.text().replace('\n', "\n\n"),
+            }),
+            WebhookFormat::Json => json!(self),
+        }
+    }
+}
+
+pub fn build_digest(state: &NotifyState, report: &Report) -> (Digest, NotifyState) {
+    let mut digest = Digest::default();
+    let mut next = NotifyState::default();
+
+    for finding in report.updates() {
+        let notified = NotifiedFinding {
+            namespace: finding.namespace.clone(),
+            application: finding.application.clone(),
+            chart: finding.chart.clone(),
+            current: finding.current.clone(),
+            latest: finding.update().unwrap().version.clone(),
+        };
+
+        let key = notified.key();
+
+        if state.notified.get(&key).map(|n| &n.latest) != Some(&notified.latest) {
+            digest.new.push(notified.clone());
+        }
+
+        next.notified.insert(key, notified);
+    }
+
+    for (key, notified) in &state.notified {
+        if next.notified.contains_key(key) {
+            continue;
+        }
+
+        let failed = report.errors.iter().any(|e| {
+            e.namespace == notified.namespace
+                && e.application == notified.application
+                && e.chart == notified.chart
+        });
+
+        if failed {
+            next.notified.insert(key.clone(), notified.clone());
+            continue;
+        }
+
+        digest.resolved.push(notified.clone());
+    }
+
+    (digest, next)
+}
+
+impl Webhook {
+    // webhook urls carry their secret in the path, so only the host is logged
+    fn host(&self) -> String {
+        url::Url::parse(&self.url)
+            .ok()
+            .and_then(|url| url.host_str().map(|host| host.to_string()))
+            .unwrap_or_else(|| "<invalid url>".to_string())
+    }
+}
+
+pub async fn send_digest(
+    client: &reqwest::Client,
+    webhook: &Webhook,
+    digest: &Digest,
+) -> anyhow::Result<()> {
+    let payload = digest.payload(webhook.format);
+
+    debug!("{}", payload);
+
+    let res = client
+        .post(&webhook.url)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .body(payload.to_string())
+        .send()
+        .await
+        .map_err(|e| anyhow!("webhook {} failed: {}", webhook.host(), e.without_url()))?;
+
+    if !res.status().is_success() {
+        bail!("webhook {} returned {}", webhook.host(), res.status());
+    }
+
+    Ok(())
+}
+
+pub struct Notifier {
+    client: reqwest::Client,
+    webhooks: Vec<Webhook>,
+    state_file: PathBuf,
+}
+
+impl Notifier {
+    pub fn new(
+        webhooks: Vec<Webhook>,
+        state_file: PathBuf,
+        connect_timeout: Duration,
+        read_timeout: Duration,
+    ) -> anyhow::Result<Self> {
+        let client = reqwest::Client::builder()
+            .user_agent(concat!("argo-helm-updater/", env!("CARGO_PKG_VERSION")))
+            .connect_timeout(connect_timeout)
+            .timeout(read_timeout)
+            .build()?;
+
+        Ok(Self {
+            client,
+            webhooks,
+            state_file,
+        })
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.webhooks.is_empty()
+    }
+
+    pub async fn notify(&self, report: &Report) -> anyhow::Result<()> {
+        if !self.is_enabled() {
+            return Ok(());
+        }
+
+        let state = NotifyState::load(&self.state_file)?;
+        let (digest, next) = build_digest(&state, report);
+
+        if digest.is_empty() {
+            debug!("nothing to notify");
+            return Ok(());
+        }
+
+        let mut errors = Vec::new();
+
+        for webhook in &self.webhooks {
+            if let Err(e) = send_digest(&self.client, webhook, &digest).await {
+                errors.push(format!("{:#}", e));
+            }
+        }
+
+        // the findings are announced again on the next run, until every webhook received them
+        if !errors.is_empty() {
+            bail!(errors.join(", "));
+        }
+
+        info!(
+            "notified {} new and {} resolved finding(s)",
+            digest.new.len(),
+            digest.resolved.len()
+        );
+
+        next.save(&self.state_file)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        helm::ChartUpdate,
+        report::{Finding, FindingKind, Report, ScanError},
+    };
+
+    use std::time::Duration;
+
+    use super::{build_digest, send_digest, Notifier, NotifyState, Webhook};
+
+    fn init_finding(application: &str, current: &str, latest: &str) -> Finding {
+        Finding {
+            resource_kind: "Application".to_owned(),
+            namespace: "argocd".to_owned(),
+            application: application.to_owned(),
+            chart: "loki".to_owned(),
+            repo: "https://grafana.github.io/helm-charts".to_owned(),
+            current: current.to_owned(),
+            kind: FindingKind::UpdateAvailable(ChartUpdate {
+                version: latest.to_owned(),
+                changelog: Vec::new(),
+                ..Default::default()
+            }),
+            owner: None,
+            location: None,
+        }
+    }
+
+    #[test]
+    fn webhook_from_str() {
+        let webhook: Webhook = "slack=https://hooks.slack.com/services/a?b=c"
+            .parse()
+            .expect("invalid webhook");
+
+        assert_eq!("https://hooks.slack.com/services/a?b=c", webhook.url);
+        assert!("discord=https://example.com".parse::<Webhook>().is_err());
+        assert!("https://example.com".parse::<Webhook>().is_err());
+    }
+
+    #[test]
+    fn build_digest_new_and_resolved() {
+        let report = Report {
+            findings: vec![
+                init_finding("loki", "5.0.0", "5.1.0"),
+                init_finding("logs", "5.0.0", "5.1.0"),
+            ],
+            errors: Vec::new(),
+            sources: Vec::new(),
+        };
+
+        let (digest, state) = build_digest(&NotifyState::default(), &report);
+        assert_eq!(2, digest.new.len());
+
+        let (digest, _) = build_digest(&state, &report);
+        assert!(digest.is_empty());
+
+        let report = Report {
+            findings: vec![init_finding("loki", "5.0.0", "5.2.0")],
+            errors: Vec::new(),
+            sources: Vec::new(),
+        };
+
+        let (digest, next) = build_digest(&state, &report);
+        assert_eq!("5.2.0", digest.new[0].latest);
+        assert_eq!("logs", digest.resolved[0].application);
+        assert_eq!(1, next.notified.len());
+    }
+
+    #[test]
+    fn build_digest_keeps_failed_findings() {
+        let report = Report {
+            findings: vec![init_finding("loki", "5.0.0", "5.1.0")],
+            errors: Vec::new(),
+            sources: Vec::new(),
+        };
+
+        let (_, state) = build_digest(&NotifyState::default(), &report);
+
+        let report = Report {
+            findings: Vec::new(),
+            errors: vec![ScanError {
+                resource_kind: "Application".to_owned(),
+                namespace: "argocd".to_owned(),
+                application: "loki".to_owned(),
+                chart: "loki".to_owned(),
+                repo: "https://grafana.github.io/helm-charts".to_owned(),
+                message: "connection refused".to_owned(),
+                location: None,
+                cause: None,
+            }],
+            sources: Vec::new(),
+        };
+
+        let (digest, next) = build_digest(&state, &report);
+        assert!(digest.is_empty());
+        assert_eq!(state, next);
+    }
+
+    #[tokio::test]
+    async fn send_digest_slack() {
+        let mut server = mockito::Server::new_async().await;
+
+        let mock = server
+            .mock("POST", "/hook")
+            .match_header("content-type", "application/json")
+            .match_body(mockito::Matcher::Json(serde_json::json!({
+                "text": "1 helm chart update(s) available:\n- argocd/loki: loki 5.0.0 -> 5.1.0",
+            })))
+            .with_status(200)
+            .create();
+
+        let report = Report {
+            findings: vec![init_finding("loki", "5.0.0", "5.1.0")],
+            errors: Vec::new(),
+            sources: Vec::new(),
+        };
+
+        let (digest, _) = build_digest(&NotifyState::default(), &report);
+        let webhook: Webhook = format!("slack={}/hook", server.url())
+            .parse()
+            .expect("invalid webhook");
+
+        let result = send_digest(&reqwest::Client::new(), &webhook, &digest).await;
+
+        mock.assert();
+        assert!(result.is_ok());
+    }
+
+    #[tokio::test]
+    async fn send_digest_error_status() {
+        let mut server = mockito::Server::new_async().await;
+
+        let mock = server.mock("POST", "/hook").with_status(500).create();
+
+        let report = Report {
+            findings: vec![init_finding("loki", "5.0.0", "5.1.0")],
+            errors: Vec::new(),
+            sources: Vec::new(),
+        };
+
+        let (digest, _) = build_digest(&NotifyState::default(), &report);
+        let webhook: Webhook = format!("json={}/hook", server.url())
+            .parse()
+            .expect("invalid webhook");
+
+        let result = send_digest(&reqwest::Client::new(), &webhook, &digest).await;
+
+        mock.assert();
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn notify_all_webhooks_on_error() {
+        let mut server = mockito::Server::new_async().await;
+
+        let failing = server.mock("POST", "/failing").with_status(500).create();
+        let working = server.mock("POST", "/working").with_status(200).create();
+
+        let webhooks: Vec<Webhook> = ["failing", "working"]
+            .iter()
+            .map(|path| format!("json={}/{}", server.url(), path).parse().unwrap())
+            .collect();
+
+        let dir = tempfile::tempdir().expect("cannot create temp dir");
+        let state_file = dir.path().join("notified.json");
+
+        let report = Report {
+            findings: vec![init_finding("loki", "5.0.0", "5.1.0")],
+            errors: Vec::new(),
+            sources: Vec::new(),
+        };
+
+        let notifier = Notifier::new(
+            webhooks,
+            state_file.clone(),
+            Duration::from_secs(1),
+            Duration::from_secs(1),
+        )
+        .expect("cannot build notifier");
+
+        let result = notifier.notify(&report).await;
+
+        failing.assert();
+        working.assert();
+
+        let message = result.unwrap_err().to_string();
+        assert_eq!(
+            "webhook 127.0.0.1 returned 500 Internal Server Error",
+            message
+        );
+        assert!(!state_file.exists());
+    }
+}
@@ -0,0 +1,1044 @@
+use std::{collections::BTreeMap, path::Path};
+
+use chrono::{DateTime, Utc};
+use clap::ValueEnum;
+use serde_json::{json, Value};
+
+use crate::{
+    manifest::ManifestLocation,
+    report::{FailOn, Finding, FindingKind, Report, ScanError, UpdateKind},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum OutputFormat {
+    Table,
+    Markdown,
+    Sarif,
+    Junit,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum GroupBy {
+    Namespace,
+    Chart,
+}
+
+const HEADER: [&str; 7] = [
+    "APPLICATION",
+    "CHART",
+    "KIND",
+    "VERSION",
+    "APP VERSION",
+    "AGE",
+    "NOTES",
+];
+
+const RESET: &str = "\x1b[0m";
+const BOLD: &str = "\x1b[1m";
+const RED: &str = "\x1b[31m";
+const GREEN: &str = "\x1b[32m";
+const YELLOW: &str = "\x1b[33m";
+const MAGENTA: &str = "\x1b[35m";
+
+fn kind_color(kind: &str) -> Option<&'static str> {
+    match kind {
+        "major" | "error" => Some(RED),
+        "minor" => Some(YELLOW),
+        "patch" => Some(GREEN),
+        "drift" => Some(MAGENTA),
+        _ => None,
+    }
+}
+
+pub fn format_age(released: DateTime<Utc>, now: DateTime<Utc>) -> String {
+    let age = now.signed_duration_since(released);
+
+    match age.num_days() {
+        days if days >= 365 => format!("{}y", days / 365),
+        days if days >= 30 => format!("{}mo", days / 30),
+        days if days >= 1 => format!("{}d", days),
+        _ if age.num_hours() >= 1 => format!("{}h", age.num_hours()),
+        _ => format!("{}m", age.num_minutes().max(0)),
+    }
+}
+
+fn finding_notes(finding: &Finding) -> Vec<String> {
+    let mut notes = Vec::new();
+
+    if let Some(owner) = &finding.owner {
+        notes.push(format!("owned by {}", owner));
+    }
+
+    if let Some(update) = finding.update() {
+        if !update.removed_values.is_empty() {
+            notes.push(format!(
+                "removed values: {}",
+                update.removed_values.join(", ")
+            ));
+        }
+
+        if let Some(sync) = &update.sync {
+            notes.push(sync.to_string());
+        }
+    }
+
+    notes
+}
+
+fn with_notes(message: String, finding: &Finding) -> String {
+    let notes = finding_notes(finding);
+
+    match notes.is_empty() {
+        true => message,
+        false => format!("{} ({})", message, notes.join("; ")),
+    }
+}
+
+fn finding_row(finding: &Finding, group_by: GroupBy, now: DateTime<Utc>) -> [String; 7] {
+    let second = match group_by {
+        GroupBy::Namespace => finding.chart.clone(),
+        GroupBy::Chart if finding.namespace.is_empty() => "-".to_string(),
+        GroupBy::Chart => finding.namespace.clone(),
+    };
+
+    match &finding.kind {
+        FindingKind::UpdateAvailable(update) => {
+            let app_version = match (&update.current_app_version, &update.app_version) {
+                (Some(current), Some(latest)) if current != latest => {
+                    format!("{} → {}", current, latest)
+                }
+                (_, Some(latest)) => latest.clone(),
+                _ => "-".to_string(),
+            };
+
+            [
+                finding.application.clone(),
+                second,
+                finding
+                    .update_kind()
+                    .unwrap_or(UpdateKind::Other)
+                    .to_string(),
+                format!("{} → {}", finding.current, update.version),
+                app_version,
+                update
+                    .released
+                    .map(|r| format_age(r, now))
+                    .unwrap_or("-".to_string()),
+                finding_notes(finding).join("; "),
+            ]
+        }
+        FindingKind::RevisionDrift(drift) => [
+            finding.application.clone(),
+            second,
+            "drift".to_string(),
+            format!(
+                "{} → {}",
+                drift.deployed.as_deref().unwrap_or("unknown"),
+                finding.current
+            ),
+            "-".to_string(),
+            "-".to_string(),
+            finding_notes(finding).join("; "),
+        ],
+    }
+}
+
+fn group_key(finding: &Finding, group_by: GroupBy) -> String {
+    let key = match group_by {
+        GroupBy::Namespace => &finding.namespace,
+        GroupBy::Chart => &finding.chart,
+    };
+
+    match key.is_empty() {
+        true => "-".to_string(),
+        false => key.clone(),
+    }
+}
+
+fn pad(value: &str, width: usize) -> String {
+    format!(
+        "{}{}",
+        value,
+        " ".repeat(width.saturating_sub(value.chars().count()))
+    )
+}
+
+fn paint(value: String, color: Option<&str>, enabled: bool) -> String {
+    match color {
+        Some(color) if enabled => format!("{}{}{}", color, value, RESET),
+        _ => value,
+    }
+}
+
+pub fn render_table(report: &Report, group_by: GroupBy, color: bool, now: DateTime<Utc>) -> String {
+    let mut groups: BTreeMap<String, Vec<[String; 7]>> = BTreeMap::new();
+
+    for finding in &report.findings {
+        groups
+            .entry(group_key(finding, group_by))
+            .or_default()
+            .push(finding_row(finding, group_by, now));
+    }
+
+    let mut header = HEADER.map(str::to_string);
+    if group_by == GroupBy::Chart {
+        header[1] = "NAMESPACE".to_string();
+    }
+
+    // the notes column is only shown when a finding has any
+    if groups.values().flatten().all(|row| row[6].is_empty()) {
+        header[6] = String::new();
+    }
+
+    let mut widths = header.clone().map(|h| h.chars().count());
+    for row in groups.values().flatten() {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let mut output = String::new();
+
+    for (group, rows) in groups.iter_mut() {
+        rows.sort();
+
+        output.push_str(&paint(group.clone(), Some(BOLD), color));
+        output.push('\n');
+
+        for (index, row) in std::iter::once(&header).chain(rows.iter()).enumerate() {
+            let cells: Vec<String> = row
+                .iter()
+                .zip(widths)
+                .enumerate()
+                .map(|(column, (cell, width))| {
+                    let cell = pad(cell, width);
+
+                    match (index, column) {
+                        (0, _) => paint(cell, Some(BOLD), color),
+                        (_, 2) => paint(cell, kind_color(row[2].as_str()), color),
+                        _ => cell,
+                    }
+                })
+                .collect();
+
+            output.push_str("  ");
+            output.push_str(cells.join("  ").trim_end());
+            output.push('\n');
+        }
+
+        output.push('\n');
+    }
+
+    for error in &report.errors {
+        output.push_str(&format!(
+            "{} [{}] {}/{} ({}): {}\n",
+            paint("error".to_string(), kind_color("error"), color),
+            error.reason(),
+            if error.namespace.is_empty() {
+                "-"
+            } else {
+                &error.namespace
+            },
+            error.application,
+            error.chart,
+            error.message
+        ));
+    }
+
+    output
+}
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct MarkdownRow {
+    application: String,
+    chart: String,
+    kind: String,
+    current: String,
+    latest: String,
+    details: Vec<String>,
+}
+
+fn escape_markdown(value: &str) -> String {
+    value.replace('|', "\\|")
+}
+
+fn markdown_finding(finding: &Finding) -> MarkdownRow {
+    let (kind, latest, details) = match &finding.kind {
+        FindingKind::UpdateAvailable(update) => (
+            finding
+                .update_kind()
+                .unwrap_or(UpdateKind::Other)
+                .to_string(),
+            update.version.clone(),
+            finding_notes(finding)
+                .iter()
+                .map(|note| format!("- {}", note))
+                .chain(update.url.iter().map(|url| format!("- download: {}", url)))
+                .chain(update.changelog.iter().map(|c| c.to_string()))
+                .collect(),
+        ),
+        FindingKind::RevisionDrift(drift) => (
+            "drift".to_string(),
+            finding.current.clone(),
+            vec![format!(
+                "- deployed: {}, sync: {}, operation: {}",
+                drift.deployed.as_deref().unwrap_or("unknown"),
+                drift.sync_status.as_deref().unwrap_or("unknown"),
+                drift.operation_phase.as_deref().unwrap_or("none"),
+            )],
+        ),
+    };
+
+    let current = match &finding.kind {
+        FindingKind::RevisionDrift(drift) => {
+            drift.deployed.clone().unwrap_or("unknown".to_string())
+        }
+        FindingKind::UpdateAvailable(_) => finding.current.clone(),
+    };
+
+    MarkdownRow {
+        application: finding.application.clone(),
+        chart: finding.chart.clone(),
+        kind,
+        current,
+        latest,
+        details,
+    }
+}
+
+fn markdown_error(error: &ScanError) -> MarkdownRow {
+    MarkdownRow {
+        application: error.application.clone(),
+        chart: error.chart.clone(),
+        kind: "error".to_string(),
+        current: "-".to_string(),
+        latest: "-".to_string(),
+        details: vec![format!("- {}: {}", error.reason(), error.message)],
+    }
+}
+
+pub fn render_markdown(report: &Report) -> String {
+    let mut counts: BTreeMap<String, usize> =
+        ["major", "minor", "patch", "other", "drift", "error"]
+            .iter()
+            .map(|k| (k.to_string(), 0))
+            .collect();
+
+    let mut namespaces: BTreeMap<String, Vec<MarkdownRow>> = BTreeMap::new();
+
+    for (namespace, namespace_report) in report.by_namespace() {
+        let rows = namespace_report
+            .findings
+            .iter()
+            .map(markdown_finding)
+            .chain(namespace_report.errors.iter().map(markdown_error));
+
+        namespaces
+            .entry(if namespace.is_empty() {
+                "-".to_string()
+            } else {
+                namespace
+            })
+            .or_default()
+            .extend(rows);
+    }
+
+    for row in namespaces.values().flatten() {
+        *counts.entry(row.kind.clone()).or_default() += 1;
+    }
+
+    let mut output = String::from("# Helm chart updates\n\n| Kind | Count |\n| --- | ---: |\n");
+
+    for kind in ["major", "minor", "patch", "other", "drift", "error"] {
+        output.push_str(&format!("| {} | {} |\n", kind, counts[kind]));
+    }
+
+    for (namespace, rows) in namespaces.iter_mut() {
+        rows.sort();
+
+        output.push_str(&format!(
+            "\n## {}\n\n| Application | Chart | Kind | Current | Latest |\n| --- | --- | --- | --- | --- |\n",
+            namespace
+        ));
+
+        for row in rows.iter() {
+            output.push_str(&format!(
+                "| {} | {} | {} | {} | {} |\n",
+                escape_markdown(&row.application),
+                escape_markdown(&row.chart),
+                row.kind,
+                escape_markdown(&row.current),
+                escape_markdown(&row.latest),
+            ));
+        }
+
+        for row in rows.iter().filter(|r| !r.details.is_empty()) {
+            output.push_str(&format!(
+                "\n<details>\n<summary>{} / {} ({})</summary>\n\n{}\n\n</details>\n",
+                row.application,
+                row.chart,
+                row.kind,
+                row.details.join("\n")
+            ));
+        }
+    }
+
+    output
+}
+
+const SARIF_ROOT: &str = "SRCROOT";
+
+fn sarif_location(
+    location: Option<&ManifestLocation>,
+    root: Option<&Path>,
+    logical_name: String,
+) -> Value {
+    let location = match location {
+        Some(location) => location,
+        None => return json!({ "logicalLocations": [{ "fullyQualifiedName": logical_name }] }),
+    };
+
+    let relative = root.and_then(|root| location.path.strip_prefix(root).ok());
+    let uri = relative
+        .unwrap_or(&location.path)
+        .to_string_lossy()
+        .replace('\\', "/");
+    let uri = uri.strip_prefix("./").unwrap_or(&uri);
+
+    let mut physical_location = match relative {
+        Some(_) => json!({ "artifactLocation": { "uri": uri, "uriBaseId": SARIF_ROOT } }),
+        None => json!({ "artifactLocation": { "uri": uri } }),
+    };
+
+    if let Some((line, column)) = location.line_column() {
+        physical_location["region"] = json!({ "startLine": line, "startColumn": column });
+    }
+
+    json!({
+        "physicalLocation": physical_location,
+        "logicalLocations": [{ "fullyQualifiedName": logical_name }],
+    })
+}
+
+fn logical_name(namespace: &str, application: &str) -> String {
+    match namespace.is_empty() {
+        true => application.to_string(),
+        false => format!("{}/{}", namespace, application),
+    }
+}
+
+pub fn render_sarif(report: &Report, root: Option<&Path>) -> String {
+    let mut results = Vec::new();
+
+    for finding in &report.findings {
+        let (rule_id, level, text) = match &finding.kind {
+            FindingKind::UpdateAvailable(update) => (
+                "chart-outdated",
+                match finding.update_kind() {
+                    Some(UpdateKind::Major) => "error",
+                    Some(UpdateKind::Minor) => "warning",
+                    _ => "note",
+                },
+                format!(
+                    "chart {} of {} {} has new version {} (current: {})",
+                    finding.chart,
+                    finding.resource_kind.to_lowercase(),
+                    finding.application,
+                    update.version,
+                    finding.current
+                ),
+            ),
+            FindingKind::RevisionDrift(drift) => (
+                "revision-drift",
+                "warning",
+                format!(
+                    "chart {} of {} {} does not run its target revision {} (deployed: {})",
+                    finding.chart,
+                    finding.resource_kind.to_lowercase(),
+                    finding.application,
+                    finding.current,
+                    drift.deployed.as_deref().unwrap_or("unknown")
+                ),
+            ),
+        };
+
+        let mut result = json!({
+            "ruleId": rule_id,
+            "level": level,
+            "message": { "text": with_notes(text, finding) },
+            "locations": [sarif_location(
+                finding.location.as_ref(),
+                root,
+                logical_name(&finding.namespace, &finding.application),
+            )],
+        });
+
+        if let Some(owner) = &finding.owner {
+            result["properties"] = json!({ "owner": owner });
+        }
+
+        results.push(result);
+    }
+
+    for error in &report.errors {
+        results.push(json!({
+            "ruleId": "chart-check-failed",
+            "level": "error",
+            "message": {
+                "text": format!("cannot check chart {} of {}: {}", error.chart, error.application, error.message),
+            },
+            "locations": [sarif_location(
+                error.location.as_ref(),
+                root,
+                logical_name(&error.namespace, &error.application),
+            )],
+            "properties": { "reason": error.reason() },
+        }));
+    }
+
+    let mut sarif = json!({
+        "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+        "version": "2.1.0",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": "argo-helm-updater",
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "informationUri": "https://github.com/dj95/argo-helm-updater",
+                    "rules": [
+                        { "id": "chart-outdated", "shortDescription": { "text": "A newer version of the helm chart is available" } },
+                        { "id": "revision-drift", "shortDescription": { "text": "The application does not run its target revision" } },
+                        { "id": "chart-check-failed", "shortDescription": { "text": "The helm chart cannot be checked" } },
+                    ],
+                },
+            },
+            "results": results,
+        }],
+    });
+
+    if let Some(root) =
+        root.and_then(|root| url::Url::from_directory_path(root.canonicalize().ok()?).ok())
+    {
+        sarif["runs"][0]["originalUriBaseIds"] = json!({ SARIF_ROOT: { "uri": root.as_str() } });
+    }
+
+    format!("{:#}\n", sarif)
+}
+
+fn escape_xml(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+pub fn render_junit(report: &Report, fail_on: FailOn) -> String {
+    let mut suites = Vec::new();
+    let (mut total_tests, mut total_failures, mut total_errors) = (0, 0, 0);
+
+    for (namespace, namespace_report) in report.by_namespace() {
+        let mut sources = namespace_report.sources.clone();
+        sources.sort_by(|a, b| (&a.application, &a.chart).cmp(&(&b.application, &b.chart)));
+
+        let mut testcases = Vec::new();
+        let (mut failures, mut errors) = (0, 0);
+
+        for source in &sources {
+            let mut children = String::new();
+
+            let failure = namespace_report
+                .updates()
+                .find(|f| source.is_finding(f) && fail_on.is_violated_by(f));
+
+            if let Some(finding) = failure {
+                failures += 1;
+                children.push_str(&format!(
+                    "      <failure type=\"{}\" message=\"{}\"/>\n",
+                    finding.update_kind().unwrap_or(UpdateKind::Other),
+                    escape_xml(&with_notes(
+                        format!(
+                            "chart {} has new version {} (current: {})",
+                            finding.chart,
+                            finding.update().unwrap().version,
+                            finding.current
+                        ),
+                        finding
+                    ))
+                ));
+            }
+
+            for error in namespace_report
+                .errors
+                .iter()
+                .filter(|e| source.is_error(e))
+            {
+                errors += 1;
+                children.push_str(&format!(
+                    "      <error type=\"{}\" message=\"{}\"/>\n",
+                    error.reason(),
+                    escape_xml(&error.message)
+                ));
+            }
+
+            let testcase = format!(
+                "    <testcase classname=\"{}\" name=\"{}\"",
+                escape_xml(&logical_name(&namespace, &source.application)),
+                escape_xml(&source.chart)
+            );
+
+            let testcase = match children.is_empty() {
+                true => format!("{}/>\n", testcase),
+                false => format!("{}>\n{}    </testcase>\n", testcase, children),
+            };
+
+            testcases.push(testcase);
+        }
+
+        total_tests += sources.len();
+        total_failures += failures;
+        total_errors += errors;
+
+        suites.push(format!(
+            "  <testsuite name=\"{}\" tests=\"{}\" failures=\"{}\" errors=\"{}\">\n{}  </testsuite>\n",
+            escape_xml(if namespace.is_empty() { "-" } else { &namespace }),
+            sources.len(),
+            failures,
+            errors,
+            testcases.concat()
+        ));
+    }
+
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<testsuites name=\"argo-helm-updater\" tests=\"{}\" failures=\"{}\" errors=\"{}\">\n{}</testsuites>\n",
+        total_tests,
+        total_failures,
+        total_errors,
+        suites.concat()
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::{Path, PathBuf};
+
+    use chrono::{DateTime, Utc};
+
+    use crate::{
+        helm::{ChangelogEntry, ChartUpdate},
+        kubernetes::{RevisionDrift, SyncOutcome, SyncReport},
+        manifest::ManifestLocation,
+        report::{FailOn, Finding, FindingKind, Report, ScanError, ScannedSource},
+    };
+
+    use super::{format_age, render_junit, render_markdown, render_sarif, render_table, GroupBy};
+
+    fn init_finding(namespace: &str, application: &str, kind: FindingKind) -> Finding {
+        Finding {
+            resource_kind: "Application".to_owned(),
+            namespace: namespace.to_owned(),
+            application: application.to_owned(),
+            chart: "cert-manager".to_owned(),
+            repo: "https://charts.jetstack.io".to_owned(),
+            current: "v1.12.0".to_owned(),
+            kind,
+            owner: None,
+            location: None,
+        }
+    }
+
+    #[test]
+    fn format_age_units() {
+        let now: DateTime<Utc> = "2024-06-01T00:00:00Z".parse().unwrap();
+
+        assert_eq!(
+            "5h",
+            format_age("2024-05-31T19:00:00Z".parse().unwrap(), now)
+        );
+        assert_eq!(
+            "3d",
+            format_age("2024-05-29T00:00:00Z".parse().unwrap(), now)
+        );
+        assert_eq!(
+            "2mo",
+            format_age("2024-03-20T00:00:00Z".parse().unwrap(), now)
+        );
+        assert_eq!(
+            "1y",
+            format_age("2023-05-01T00:00:00Z".parse().unwrap(), now)
+        );
+    }
+
+    #[test]
+    fn render_table_grouped_by_namespace() {
+        let now: DateTime<Utc> = "2024-06-01T00:00:00Z".parse().unwrap();
+
+        let report = Report {
+            findings: vec![
+                init_finding(
+                    "monitoring",
+                    "certs",
+                    FindingKind::UpdateAvailable(ChartUpdate {
+                        version: "v1.13.1".to_owned(),
+                        current_app_version: Some("v1.12.0".to_owned()),
+                        app_version: Some("v1.13.1".to_owned()),
+                        released: Some("2024-05-29T00:00:00Z".parse().unwrap()),
+                        ..Default::default()
+                    }),
+                ),
+                init_finding(
+                    "argocd",
+                    "cert-manager",
+                    FindingKind::RevisionDrift(RevisionDrift {
+                        deployed: Some("v1.11.0".to_owned()),
+                        sync_status: Some("OutOfSync".to_owned()),
+                        operation_phase: None,
+                    }),
+                ),
+            ],
+            errors: Vec::new(),
+            sources: Vec::new(),
+        };
+
+        let result = render_table(&report, GroupBy::Namespace, false, now);
+
+        assert_eq!(
+            "argocd
+  APPLICATION   CHART         KIND   VERSION            APP VERSION        AGE
+  cert-manager  cert-manager  drift  v1.11.0 → v1.12.0  -                  -
+
+monitoring
+  APPLICATION   CHART         KIND   VERSION            APP VERSION        AGE
+  certs         cert-manager  minor  v1.12.0 → v1.13.1  v1.12.0 → v1.13.1  3d
+
+",
+            result
+        );
+    }
+
+    #[test]
+    fn render_markdown_per_namespace() {
+        let report = Report {
+            findings: vec![init_finding(
+                "argocd",
+                "cert-manager",
+                FindingKind::UpdateAvailable(ChartUpdate {
+                    version: "v2.0.0".to_owned(),
+                    changelog: vec![ChangelogEntry {
+                        version: "v2.0.0".to_owned(),
+                        kind: Some("changed".to_owned()),
+                        description: "drop legacy CRDs".to_owned(),
+                    }],
+                    ..Default::default()
+                }),
+            )],
+            errors: vec![ScanError {
+                resource_kind: "Application".to_owned(),
+                namespace: "argocd".to_owned(),
+                application: "loki".to_owned(),
+                chart: "loki".to_owned(),
+                repo: "https://grafana.github.io/helm-charts".to_owned(),
+                message: "cannot find chart".to_owned(),
+                location: None,
+                cause: None,
+            }],
+            sources: Vec::new(),
+        };
+
+        assert_eq!(
+            "# Helm chart updates
+
+| Kind | Count |
+| --- | ---: |
+| major | 1 |
+| minor | 0 |
+| patch | 0 |
+| other | 0 |
+| drift | 0 |
+| error | 1 |
+
+## argocd
+
+| Application | Chart | Kind | Current | Latest |
+| --- | --- | --- | --- | --- |
+| cert-manager | cert-manager | major | v1.12.0 | v2.0.0 |
+| loki | loki | error | - | - |
+
+<details>
+<summary>cert-manager / cert-manager (major)</summary>
+
+- v2.0.0 (changed): drop legacy CRDs
+
+</details>
+
+<details>
+<summary>loki / loki (error)</summary>
+
+- unknown: cannot find chart
+
+</details>
+",
+            render_markdown(&report)
+        );
+    }
+
+    #[test]
+    fn render_junit_fails_beyond_policy() {
+        let source = |application: &str| ScannedSource {
+            resource_kind: "Application".to_owned(),
+            namespace: "argocd".to_owned(),
+            application: application.to_owned(),
+            chart: "cert-manager".to_owned(),
+            current: "v1.12.0".to_owned(),
+            location: None,
+        };
+
+        let report = Report {
+            findings: vec![
+                init_finding(
+                    "argocd",
+                    "major",
+                    FindingKind::UpdateAvailable(ChartUpdate {
+                        version: "v2.0.0".to_owned(),
+                        ..Default::default()
+                    }),
+                ),
+                init_finding(
+                    "argocd",
+                    "patch",
+                    FindingKind::UpdateAvailable(ChartUpdate {
+                        version: "v1.12.1".to_owned(),
+                        ..Default::default()
+                    }),
+                ),
+            ],
+            errors: Vec::new(),
+            sources: vec![source("patch"), source("major"), source("current")],
+        };
+
+        assert_eq!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<testsuites name=\"argo-helm-updater\" tests=\"3\" failures=\"1\" errors=\"0\">
+  <testsuite name=\"argocd\" tests=\"3\" failures=\"1\" errors=\"0\">
+    <testcase classname=\"argocd/current\" name=\"cert-manager\"/>
+    <testcase classname=\"argocd/major\" name=\"cert-manager\">
+      <failure type=\"major\" message=\"chart cert-manager has new version v2.0.0 (current: v1.12.0)\"/>
+    </testcase>
+    <testcase classname=\"argocd/patch\" name=\"cert-manager\"/>
+  </testsuite>
+</testsuites>
+",
+            render_junit(&report, FailOn::Minor)
+        );
+    }
+
+    #[test]
+    fn render_sync_outcome_notes() {
+        let now: DateTime<Utc> = "2024-06-01T00:00:00Z".parse().unwrap();
+
+        let report = Report {
+            findings: vec![init_finding(
+                "argocd",
+                "certs",
+                FindingKind::UpdateAvailable(ChartUpdate {
+                    version: "v1.13.1".to_owned(),
+                    sync: Some(SyncReport {
+                        outcome: SyncOutcome::Failed("hook failed".to_owned()),
+                        rollback: Some(("v1.12.0".to_owned(), SyncOutcome::Succeeded)),
+                    }),
+                    ..Default::default()
+                }),
+            )],
+            errors: Vec::new(),
+            sources: Vec::new(),
+        };
+
+        let note = "sync failed: hook failed, rolled back to v1.12.0 (sync succeeded)";
+
+        assert_eq!(
+            format!(
+                "argocd
+  APPLICATION  CHART         KIND   VERSION            APP VERSION  AGE  NOTES
+  certs        cert-manager  minor  v1.12.0 → v1.13.1  -            -    {}
+
+",
+                note
+            ),
+            render_table(&report, GroupBy::Namespace, false, now)
+        );
+        assert!(render_markdown(&report).contains(&format!("- {}\n", note)));
+
+        let sarif: serde_json::Value =
+            serde_json::from_str(&render_sarif(&report, None)).expect("invalid sarif");
+        assert!(sarif["runs"][0]["results"][0]["message"]["text"]
+            .as_str()
+            .unwrap()
+            .ends_with(&format!("({})", note)));
+    }
+
+    #[test]
+    fn render_owner_of_finding() {
+        let now: DateTime<Utc> = "2024-06-01T00:00:00Z".parse().unwrap();
+
+        let report = Report {
+            findings: vec![init_finding(
+                "argocd",
+                "certs",
+                FindingKind::UpdateAvailable(ChartUpdate {
+                    version: "v1.13.1".to_owned(),
+                    ..Default::default()
+                }),
+            )
+            .with_owner(Some("ApplicationSet/argocd/certs".to_owned()))],
+            errors: Vec::new(),
+            sources: Vec::new(),
+        };
+
+        let note = "owned by ApplicationSet/argocd/certs";
+
+        assert!(render_table(&report, GroupBy::Namespace, false, now).contains(note));
+        assert!(render_markdown(&report).contains(&format!("- {}\n", note)));
+
+        let sarif: serde_json::Value =
+            serde_json::from_str(&render_sarif(&report, None)).expect("invalid sarif");
+        let result = &sarif["runs"][0]["results"][0];
+        assert_eq!("ApplicationSet/argocd/certs", result["properties"]["owner"]);
+        assert!(result["message"]["text"]
+            .as_str()
+            .unwrap()
+            .ends_with(&format!("({})", note)));
+    }
+
+    #[test]
+    fn render_removed_values_notes() {
+        let now: DateTime<Utc> = "2024-06-01T00:00:00Z".parse().unwrap();
+
+        let report = Report {
+            findings: vec![init_finding(
+                "argocd",
+                "certs",
+                FindingKind::UpdateAvailable(ChartUpdate {
+                    version: "v1.13.1".to_owned(),
+                    removed_values: vec!["installCRDs".to_owned(), "legacy.enabled".to_owned()],
+                    ..Default::default()
+                }),
+            )],
+            errors: Vec::new(),
+            sources: vec![ScannedSource {
+                resource_kind: "Application".to_owned(),
+                namespace: "argocd".to_owned(),
+                application: "certs".to_owned(),
+                chart: "cert-manager".to_owned(),
+                current: "v1.12.0".to_owned(),
+                location: None,
+            }],
+        };
+
+        let note = "removed values: installCRDs, legacy.enabled";
+
+        assert!(render_table(&report, GroupBy::Namespace, false, now).contains(note));
+        assert!(render_markdown(&report).contains(&format!("- {}\n", note)));
+        assert!(render_junit(&report, FailOn::Any).contains(note));
+
+        let sarif: serde_json::Value =
+            serde_json::from_str(&render_sarif(&report, None)).expect("invalid sarif");
+        assert!(sarif["runs"][0]["results"][0]["message"]["text"]
+            .as_str()
+            .unwrap()
+            .ends_with(&format!("({})", note)));
+    }
+
+    #[test]
+    fn render_junit_of_merged_application_reports() {
+        let app_report = |application: &str, version: &str| Report {
+            findings: vec![init_finding(
+                "argocd",
+                application,
+                FindingKind::UpdateAvailable(ChartUpdate {
+                    version: version.to_owned(),
+                    ..Default::default()
+                }),
+            )],
+            errors: Vec::new(),
+            sources: vec![ScannedSource {
+                resource_kind: "Application".to_owned(),
+                namespace: "argocd".to_owned(),
+                application: application.to_owned(),
+                chart: "cert-manager".to_owned(),
+                current: "v1.12.0".to_owned(),
+                location: None,
+            }],
+        };
+
+        let mut report = Report::default();
+        report.merge(app_report("loki", "v1.12.1"));
+        report.merge(app_report("logs", "v2.0.0"));
+
+        let junit = render_junit(&report, FailOn::Any);
+
+        assert!(
+            junit.contains("<testsuite name=\"argocd\" tests=\"2\" failures=\"2\" errors=\"0\">")
+        );
+        assert!(junit.contains("<testcase classname=\"argocd/loki\" name=\"cert-manager\">"));
+        assert!(junit.contains("<testcase classname=\"argocd/logs\" name=\"cert-manager\">"));
+    }
+
+    #[test]
+    fn render_sarif_levels() {
+        let report = Report {
+            findings: vec![init_finding(
+                "argocd",
+                "cert-manager",
+                FindingKind::UpdateAvailable(ChartUpdate {
+                    version: "v2.0.0".to_owned(),
+                    ..Default::default()
+                }),
+            )],
+            errors: Vec::new(),
+            sources: Vec::new(),
+        };
+
+        let sarif: serde_json::Value =
+            serde_json::from_str(&render_sarif(&report, None)).expect("invalid sarif");
+        let result = &sarif["runs"][0]["results"][0];
+
+        assert_eq!("chart-outdated", result["ruleId"]);
+        assert_eq!("error", result["level"]);
+        assert_eq!(
+            "argocd/cert-manager",
+            result["locations"][0]["logicalLocations"][0]["fullyQualifiedName"]
+        );
+    }
+
+    #[test]
+    fn render_sarif_relative_to_root() {
+        let mut finding = init_finding(
+            "",
+            "loki",
+            FindingKind::UpdateAvailable(ChartUpdate {
+                version: "v2.0.0".to_owned(),
+                ..Default::default()
+            }),
+        );
+        finding.location = Some(ManifestLocation {
+            path: PathBuf::from("gitops/apps/loki.yaml"),
+            document: 0,
+            yaml_path: Vec::new(),
+        });
+
+        let report = Report {
+            findings: vec![finding],
+            errors: Vec::new(),
+            sources: Vec::new(),
+        };
+
+        let sarif: serde_json::Value =
+            serde_json::from_str(&render_sarif(&report, Some(Path::new("gitops"))))
+                .expect("invalid sarif");
+        let artifact =
+            &sarif["runs"][0]["results"][0]["locations"][0]["physicalLocation"]["artifactLocation"];
+
+        assert_eq!("apps/loki.yaml", artifact["uri"]);
+        assert_eq!("SRCROOT", artifact["uriBaseId"]);
+    }
+}
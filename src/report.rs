@@ -0,0 +1,544 @@
+use std::{collections::BTreeMap, fmt::Display};
+
+use chrono::{DateTime, Utc};
+use clap::ValueEnum;
+use kube::{Resource, ResourceExt};
+
+use crate::{
+    error::CheckError,
+    helm::{parse_semver, ChartUpdate, HelmChart},
+    kubernetes::{
+        ChartUpdateReportSpec, ReportedError, ReportedFinding, RevisionDrift, SourceSpec,
+    },
+    manifest::{ManifestChart, ManifestLocation},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum UpdateKind {
+    Patch,
+    Minor,
+    Major,
+    Other,
+}
+
+impl Display for UpdateKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UpdateKind::Patch => write!(f, "patch"),
+            UpdateKind::Minor => write!(f, "minor"),
+            UpdateKind::Major => write!(f, "major"),
+            UpdateKind::Other => write!(f, "other"),
+        }
+    }
+}
+
+pub fn update_kind(current: &str, latest: &str) -> UpdateKind {
+    match (parse_semver(current), parse_semver(latest)) {
+        (Some(current), Some(latest)) if current.major != latest.major => UpdateKind::Major,
+        (Some(current), Some(latest)) if current.minor != latest.minor => UpdateKind::Minor,
+        (Some(_), Some(_)) => UpdateKind::Patch,
+        _ => UpdateKind::Other,
+    }
+}
+
+// clap exits with 2 on usage errors, so the codes of the scan stay clear of it
+pub const EXIT_UPDATES_AVAILABLE: u8 = 10;
+pub const EXIT_ERRORS_OCCURRED: u8 = 11;
+pub const EXIT_SCAN_FAILED: u8 = 12;
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum FailOn {
+    Patch,
+    Minor,
+    Major,
+    Any,
+    Error,
+}
+
+impl FailOn {
+    pub fn is_violated_by(&self, finding: &Finding) -> bool {
+        let kind = match finding.update_kind() {
+            Some(kind) => kind,
+            None => return false,
+        };
+
+        match self {
+            FailOn::Patch => kind != UpdateKind::Other,
+            FailOn::Minor => matches!(kind, UpdateKind::Minor | UpdateKind::Major),
+            FailOn::Major => kind == UpdateKind::Major,
+            FailOn::Any => true,
+            FailOn::Error => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FindingKind {
+    UpdateAvailable(ChartUpdate),
+    RevisionDrift(RevisionDrift),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Finding {
+    pub resource_kind: String,
+    pub namespace: String,
+    pub application: String,
+    pub chart: String,
+    pub repo: String,
+    pub current: String,
+    pub kind: FindingKind,
+    pub owner: Option<String>,
+    pub location: Option<ManifestLocation>,
+}
+
+impl Finding {
+    pub fn new<K>(resource: &K, helm: &HelmChart, kind: FindingKind) -> Self
+    where
+        K: Resource<DynamicType = ()>,
+    {
+        Self {
+            resource_kind: K::kind(&()).to_string(),
+            namespace: resource.namespace().unwrap_or_default(),
+            application: resource.name_any(),
+            chart: helm.chart.clone(),
+            repo: helm.repo.clone(),
+            current: helm.revision.clone(),
+            kind,
+            owner: None,
+            location: None,
+        }
+    }
+
+    pub fn from_manifest_chart(manifest_chart: &ManifestChart, kind: FindingKind) -> Self {
+        Self {
+            resource_kind: manifest_chart.kind.clone(),
+            namespace: String::new(),
+            application: manifest_chart.name.clone(),
+            chart: manifest_chart.helm.chart.clone(),
+            repo: manifest_chart.helm.repo.clone(),
+            current: manifest_chart.helm.revision.clone(),
+            kind,
+            owner: None,
+            location: Some(manifest_chart.location.clone()),
+        }
+    }
+
+    pub fn with_owner(mut self, owner: Option<String>) -> Self {
+        self.owner = owner;
+        self
+    }
+
+    pub fn with_location(mut self, location: ManifestLocation) -> Self {
+        self.location = Some(location);
+        self
+    }
+
+    pub fn is_update(&self) -> bool {
+        matches!(self.kind, FindingKind::UpdateAvailable(_))
+    }
+
+    pub fn is_drift(&self) -> bool {
+        matches!(self.kind, FindingKind::RevisionDrift(_))
+    }
+
+    pub fn update(&self) -> Option<&ChartUpdate> {
+        match &self.kind {
+            FindingKind::UpdateAvailable(update) => Some(update),
+            FindingKind::RevisionDrift(_) => None,
+        }
+    }
+
+    pub fn update_kind(&self) -> Option<UpdateKind> {
+        self.update()
+            .map(|update| update_kind(&self.current, &update.version))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScanError {
+    pub resource_kind: String,
+    pub namespace: String,
+    pub application: String,
+    pub chart: String,
+    pub repo: String,
+    pub message: String,
+    pub location: Option<ManifestLocation>,
+    pub cause: Option<CheckError>,
+}
+
+impl ScanError {
+    pub fn new<K>(resource: &K, source_spec: &SourceSpec, error: &anyhow::Error) -> Self
+    where
+        K: Resource<DynamicType = ()>,
+    {
+        Self {
+            resource_kind: K::kind(&()).to_string(),
+            namespace: resource.namespace().unwrap_or_default(),
+            application: resource.name_any(),
+            chart: source_spec.chart.clone().unwrap_or_default(),
+            repo: source_spec.repo_url.clone().unwrap_or_default(),
+            message: format!("{:#}", error),
+            location: None,
+            cause: error.downcast_ref::<CheckError>().cloned(),
+        }
+    }
+
+    pub fn from_manifest_chart(manifest_chart: &ManifestChart, error: &anyhow::Error) -> Self {
+        Self {
+            resource_kind: manifest_chart.kind.clone(),
+            namespace: String::new(),
+            application: manifest_chart.name.clone(),
+            chart: manifest_chart.helm.chart.clone(),
+            repo: manifest_chart.helm.repo.clone(),
+            message: format!("{:#}", error),
+            location: Some(manifest_chart.location.clone()),
+            cause: error.downcast_ref::<CheckError>().cloned(),
+        }
+    }
+
+    pub fn with_location(mut self, location: ManifestLocation) -> Self {
+        self.location = Some(location);
+        self
+    }
+
+    pub fn reason(&self) -> &'static str {
+        self.cause.as_ref().map(|c| c.code()).unwrap_or("unknown")
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScannedSource {
+    pub resource_kind: String,
+    pub namespace: String,
+    pub application: String,
+    pub chart: String,
+    pub current: String,
+    pub location: Option<ManifestLocation>,
+}
+
+impl ScannedSource {
+    pub fn new<K>(resource: &K, source_spec: &SourceSpec) -> Option<Self>
+    where
+        K: Resource<DynamicType = ()>,
+    {
+        let helm = HelmChart::try_from(source_spec.clone()).ok()?;
+
+        Some(Self {
+            resource_kind: K::kind(&()).to_string(),
+            namespace: resource.namespace().unwrap_or_default(),
+            application: resource.name_any(),
+            chart: helm.chart,
+            current: helm.revision,
+            location: None,
+        })
+    }
+
+    pub fn from_manifest_chart(manifest_chart: &ManifestChart) -> Self {
+        Self {
+            resource_kind: manifest_chart.kind.clone(),
+            namespace: String::new(),
+            application: manifest_chart.name.clone(),
+            chart: manifest_chart.helm.chart.clone(),
+            current: manifest_chart.helm.revision.clone(),
+            location: Some(manifest_chart.location.clone()),
+        }
+    }
+
+    pub fn with_location(mut self, location: ManifestLocation) -> Self {
+        self.location = Some(location);
+        self
+    }
+
+    pub fn is_finding(&self, finding: &Finding) -> bool {
+        self.resource_kind == finding.resource_kind
+            && self.namespace == finding.namespace
+            && self.application == finding.application
+            && self.chart == finding.chart
+            && self.current == finding.current
+    }
+
+    pub fn is_error(&self, error: &ScanError) -> bool {
+        self.resource_kind == error.resource_kind
+            && self.namespace == error.namespace
+            && self.application == error.application
+            && self.chart == error.chart
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Report {
+    pub findings: Vec<Finding>,
+    pub errors: Vec<ScanError>,
+    pub sources: Vec<ScannedSource>,
+}
+
+impl Report {
+    pub fn merge(&mut self, other: Report) {
+        self.findings.extend(other.findings);
+        self.errors.extend(other.errors);
+        self.sources.extend(other.sources);
+    }
+
+    pub fn updates(&self) -> impl Iterator<Item = &Finding> {
+        self.findings.iter().filter(|f| f.is_update())
+    }
+
+    pub fn drifts(&self) -> impl Iterator<Item = &Finding> {
+        self.findings.iter().filter(|f| f.is_drift())
+    }
+
+    pub fn exit_code(&self, fail_on: Option<FailOn>) -> u8 {
+        if !self.errors.is_empty() {
+            return EXIT_ERRORS_OCCURRED;
+        }
+
+        let failed = match fail_on {
+            Some(fail_on) => self.updates().any(|f| fail_on.is_violated_by(f)),
+            None => self.updates().next().is_some(),
+        };
+
+        match failed {
+            true => EXIT_UPDATES_AVAILABLE,
+            false => 0,
+        }
+    }
+
+    pub fn by_namespace(&self) -> BTreeMap<String, Report> {
+        let mut output: BTreeMap<String, Report> = BTreeMap::new();
+
+        for finding in &self.findings {
+            output
+                .entry(finding.namespace.clone())
+                .or_default()
+                .findings
+                .push(finding.clone());
+        }
+
+        for error in &self.errors {
+            output
+                .entry(error.namespace.clone())
+                .or_default()
+                .errors
+                .push(error.clone());
+        }
+
+        for source in &self.sources {
+            output
+                .entry(source.namespace.clone())
+                .or_default()
+                .sources
+                .push(source.clone());
+        }
+
+        output
+    }
+
+    pub fn to_report_spec(&self, generated_at: DateTime<Utc>) -> ChartUpdateReportSpec {
+        let findings = self
+            .findings
+            .iter()
+            .map(|f| ReportedFinding {
+                kind: match f.kind {
+                    FindingKind::UpdateAvailable(_) => "UpdateAvailable".to_string(),
+                    FindingKind::RevisionDrift(_) => "RevisionDrift".to_string(),
+                },
+                resource_kind: f.resource_kind.clone(),
+                application: f.application.clone(),
+                chart: f.chart.clone(),
+                repo: f.repo.clone(),
+                current: f.current.clone(),
+                latest: f.update().map(|u| u.version.clone()),
+                update_kind: f.update_kind().map(|k| k.to_string()),
+                deployed: match &f.kind {
+                    FindingKind::RevisionDrift(drift) => drift.deployed.clone(),
+                    FindingKind::UpdateAvailable(_) => None,
+                },
+                first_seen: generated_at,
+            })
+            .collect();
+
+        let errors = self
+            .errors
+            .iter()
+            .map(|e| ReportedError {
+                reason: e.reason().to_string(),
+                resource_kind: e.resource_kind.clone(),
+                application: e.application.clone(),
+                chart: e.chart.clone(),
+                repo: e.repo.clone(),
+                message: e.message.clone(),
+            })
+            .collect();
+
+        ChartUpdateReportSpec {
+            generated_at,
+            findings,
+            errors,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FindingsStore {
+    reports: BTreeMap<String, Report>,
+}
+
+impl FindingsStore {
+    pub fn insert(&mut self, key: String, report: Report) {
+        self.reports.insert(key, report);
+    }
+
+    pub fn remove(&mut self, key: &str) {
+        self.reports.remove(key);
+    }
+
+    pub fn report(&self) -> Report {
+        let mut output = Report::default();
+
+        for report in self.reports.values() {
+            output.merge(report.clone());
+        }
+
+        output
+    }
+}
+
+impl From<Report> for FindingsStore {
+    fn from(report: Report) -> Self {
+        let mut store = FindingsStore::default();
+
+        for finding in report.findings {
+            let key = format!("{}/{}", finding.namespace, finding.application);
+            store.reports.entry(key).or_default().findings.push(finding);
+        }
+
+        for error in report.errors {
+            let key = format!("{}/{}", error.namespace, error.application);
+            store.reports.entry(key).or_default().errors.push(error);
+        }
+
+        for source in report.sources {
+            let key = format!("{}/{}", source.namespace, source.application);
+            store.reports.entry(key).or_default().sources.push(source);
+        }
+
+        store
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::Utc;
+
+    use crate::helm::ChartUpdate;
+
+    use super::{
+        update_kind, FailOn, Finding, FindingKind, FindingsStore, Report, ScanError, UpdateKind,
+        EXIT_ERRORS_OCCURRED, EXIT_UPDATES_AVAILABLE,
+    };
+
+    fn init_finding(namespace: &str, application: &str) -> Finding {
+        Finding {
+            resource_kind: "Application".to_owned(),
+            namespace: namespace.to_owned(),
+            application: application.to_owned(),
+            chart: "loki".to_owned(),
+            repo: "https://grafana.github.io/helm-charts".to_owned(),
+            current: "5.0.0".to_owned(),
+            kind: FindingKind::UpdateAvailable(ChartUpdate {
+                version: "5.1.0".to_owned(),
+                changelog: Vec::new(),
+                ..Default::default()
+            }),
+            owner: None,
+            location: None,
+        }
+    }
+
+    #[test]
+    fn report_exit_code() {
+        let mut report = Report {
+            findings: vec![init_finding("argocd", "loki")],
+            errors: Vec::new(),
+            sources: Vec::new(),
+        };
+
+        assert_eq!(EXIT_UPDATES_AVAILABLE, report.exit_code(None));
+        assert_eq!(
+            EXIT_UPDATES_AVAILABLE,
+            report.exit_code(Some(FailOn::Minor))
+        );
+        assert_eq!(0, report.exit_code(Some(FailOn::Major)));
+        assert_eq!(0, report.exit_code(Some(FailOn::Error)));
+        assert_eq!(0, Report::default().exit_code(None));
+
+        report.errors.push(ScanError {
+            resource_kind: "Application".to_owned(),
+            namespace: "argocd".to_owned(),
+            application: "logs".to_owned(),
+            chart: "loki".to_owned(),
+            repo: "https://grafana.github.io/helm-charts".to_owned(),
+            message: "connection refused".to_owned(),
+            location: None,
+            cause: None,
+        });
+
+        assert_eq!(EXIT_ERRORS_OCCURRED, report.exit_code(Some(FailOn::Major)));
+    }
+
+    #[test]
+    fn report_spec_preserves_first_seen() {
+        let report = Report {
+            findings: vec![
+                init_finding("argocd", "loki"),
+                init_finding("monitoring", "logs"),
+            ],
+            errors: Vec::new(),
+            sources: Vec::new(),
+        };
+
+        let namespaces = report.by_namespace();
+        assert_eq!(
+            vec!["argocd", "monitoring"],
+            namespaces.keys().collect::<Vec<_>>()
+        );
+
+        let first_seen = "2024-01-01T00:00:00Z".parse().unwrap();
+        let previous = namespaces["argocd"].to_report_spec(first_seen);
+
+        let mut spec = namespaces["argocd"].to_report_spec(Utc::now());
+        spec.preserve_first_seen(&previous);
+
+        assert_eq!(first_seen, spec.findings[0].first_seen);
+        assert_eq!(Some("minor".to_owned()), spec.findings[0].update_kind);
+    }
+
+    #[test]
+    fn findings_store_replaces_applications() {
+        let mut store = FindingsStore::from(Report {
+            findings: vec![
+                init_finding("argocd", "loki"),
+                init_finding("argocd", "logs"),
+            ],
+            errors: Vec::new(),
+            sources: Vec::new(),
+        });
+
+        store.insert("argocd/loki".to_owned(), Report::default());
+        assert_eq!(
+            vec![init_finding("argocd", "logs")],
+            store.report().findings
+        );
+
+        store.remove("argocd/logs");
+        assert!(store.report().findings.is_empty());
+    }
+
+    #[test]
+    fn update_kind_from_versions() {
+        assert_eq!(UpdateKind::Major, update_kind("v1.12.0", "v2.0.0"));
+        assert_eq!(UpdateKind::Minor, update_kind("1.12.0", "1.13.1"));
+        assert_eq!(UpdateKind::Patch, update_kind("1.12.0", "1.12.3"));
+        assert_eq!(UpdateKind::Other, update_kind("latest", "1.12.3"));
+    }
+}
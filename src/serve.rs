@@ -0,0 +1,128 @@
+use std::{
+    convert::Infallible,
+    net::SocketAddr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use anyhow::bail;
+use http_body_util::Full;
+use hyper::{body::Bytes, server::conn::http1, service::service_fn, Request, Response, StatusCode};
+use hyper_util::rt::TokioIo;
+use log::{error, info};
+use tokio::{net::TcpListener, sync::RwLock};
+
+use crate::{metrics::Metrics, scan, Args};
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct ServeArgs {
+    #[arg(
+        long,
+        default_value = "0.0.0.0:9090",
+        help = "Address to serve /metrics, /healthz and /readyz on"
+    )]
+    listen: SocketAddr,
+
+    #[arg(long, default_value_t = 3600, help = "Seconds between two scans")]
+    interval: u64,
+}
+
+fn response(status: StatusCode, body: String) -> Response<Full<Bytes>> {
+    let mut response = Response::new(Full::new(Bytes::from(body)));
+    *response.status_mut() = status;
+
+    response
+}
+
+async fn handle(
+    metrics: Arc<RwLock<Metrics>>,
+    request: Request<hyper::body::Incoming>,
+) -> Result<Response<Full<Bytes>>, Infallible> {
+    let metrics = metrics.read().await;
+
+    let response = match request.uri().path() {
+        "/metrics" => {
+            let mut response = response(StatusCode::OK, metrics.render());
+            response.headers_mut().insert(
+                hyper::header::CONTENT_TYPE,
+                "text/plain; version=0.0.4".parse().unwrap(),
+            );
+
+            response
+        }
+        "/healthz" => response(StatusCode::OK, "ok\n".to_string()),
+        "/readyz" if metrics.is_ready() => response(StatusCode::OK, "ok\n".to_string()),
+        "/readyz" => response(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "waiting for first scan\n".to_string(),
+        ),
+        _ => response(StatusCode::NOT_FOUND, "not found\n".to_string()),
+    };
+
+    Ok(response)
+}
+
+async fn scan_loop(args: Args, interval: Duration, metrics: Arc<RwLock<Metrics>>) {
+    let mut interval = tokio::time::interval(interval);
+
+    loop {
+        interval.tick().await;
+
+        let start = Instant::now();
+        let result = scan(&args).await;
+        let duration = start.elapsed();
+
+        match result {
+            Ok(report) => {
+                info!(
+                    "scan finished in {:.1}s, found {} outdated chart(s)",
+                    duration.as_secs_f64(),
+                    report.updates().count()
+                );
+
+                metrics.write().await.record_scan(report, duration);
+            }
+            Err(e) => {
+                error!("scan failed: {:?}", e);
+                metrics.write().await.record_failure(duration);
+            }
+        }
+    }
+}
+
+pub async fn serve(args: Args, serve_args: ServeArgs) -> anyhow::Result<()> {
+    if args.update {
+        bail!("--update cannot be used with serve");
+    }
+
+    let metrics = Arc::new(RwLock::new(Metrics::default()));
+
+    tokio::spawn(scan_loop(
+        args,
+        Duration::from_secs(serve_args.interval),
+        metrics.clone(),
+    ));
+
+    serve_http(serve_args.listen, metrics).await
+}
+
+pub async fn serve_http(listen: SocketAddr, metrics: Arc<RwLock<Metrics>>) -> anyhow::Result<()> {
+    let listener = TcpListener::bind(listen).await?;
+    info!("serving metrics on http://{}/metrics", listen);
+
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let metrics = metrics.clone();
+
+        tokio::spawn(async move {
+            let service = service_fn(move |request| handle(metrics.clone(), request));
+
+            if let Err(e) = http1::Builder::new()
+                .serve_connection(TokioIo::new(stream), service)
+                .await
+            {
+                error!("cannot serve connection: {:?}", e);
+            }
+        });
+    }
+}
@@ -0,0 +1,248 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use anyhow::bail;
+use log::debug;
+use serde_yaml::Value;
+
+use crate::kubernetes::{ApplicationSpec, SourceSpec};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Checkout {
+    pub repo_url: String,
+    pub path: PathBuf,
+}
+
+impl FromStr for Checkout {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.rsplit_once('=') {
+            Some((repo_url, path)) if !repo_url.is_empty() && !path.is_empty() => Ok(Self {
+                repo_url: repo_url.to_string(),
+                path: PathBuf::from(path),
+            }),
+            _ => bail!("expected <repoURL>=<path>, got '{}'", s),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Checkouts {
+    pub default: PathBuf,
+    pub repos: Vec<Checkout>,
+}
+
+fn normalize_repo_url(repo_url: &str) -> &str {
+    let repo_url = repo_url.trim_end_matches('/');
+
+    repo_url.strip_suffix(".git").unwrap_or(repo_url)
+}
+
+impl Checkouts {
+    pub fn path_for(&self, repo_url: &str) -> &Path {
+        self.repos
+            .iter()
+            .find(|c| normalize_repo_url(&c.repo_url) == normalize_repo_url(repo_url))
+            .map(|c| c.path.as_path())
+            .unwrap_or(&self.default)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResolvedValues {
+    pub values: Value,
+    pub files: Vec<PathBuf>,
+    pub missing: Vec<String>,
+}
+
+pub fn merge_values(base: &mut Value, overlay: Value) {
+    match (base, overlay) {
+        (Value::Mapping(base), Value::Mapping(overlay)) => {
+            for (key, value) in overlay {
+                match base.get_mut(&key) {
+                    Some(existing) => merge_values(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, overlay) => *base = overlay,
+    }
+}
+
+fn resolve_value_file(
+    spec: &ApplicationSpec,
+    source: &SourceSpec,
+    value_file: &str,
+    checkouts: &Checkouts,
+) -> Option<PathBuf> {
+    if let Some(reference) = value_file.strip_prefix('$') {
+        let (name, path) = reference.split_once('/')?;
+
+        let ref_source = spec
+            .sources
+            .as_ref()?
+            .iter()
+            .find(|s| s.reference.as_ref().and_then(|r| r.as_str()) == Some(name))?;
+
+        let checkout = checkouts.path_for(ref_source.repo_url.as_deref().unwrap_or_default());
+
+        return Some(checkout.join(path));
+    }
+
+    let source_path = source.path.as_ref()?;
+    let checkout = checkouts.path_for(source.repo_url.as_deref().unwrap_or_default());
+
+    Some(checkout.join(source_path).join(value_file))
+}
+
+pub fn resolve_values(
+    spec: &ApplicationSpec,
+    source: &SourceSpec,
+    checkouts: &Checkouts,
+) -> ResolvedValues {
+    let mut output = ResolvedValues {
+        values: Value::Mapping(Default::default()),
+        files: Vec::new(),
+        missing: Vec::new(),
+    };
+
+    let helm = match &source.helm {
+        Some(helm) => helm,
+        None => return output,
+    };
+
+    let value_files = helm
+        .get("valueFiles")
+        .and_then(|v| v.as_array())
+        .cloned()
+        .unwrap_or_default();
+
+    let ignore_missing = helm
+        .get("ignoreMissingValueFiles")
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false);
+
+    for value_file in value_files.iter().filter_map(|v| v.as_str()) {
+        let path = match resolve_value_file(spec, source, value_file, checkouts) {
+            Some(path) => path,
+            None => {
+                debug!("cannot resolve value file {} locally", value_file);
+                continue;
+            }
+        };
+
+        let values = fs::read_to_string(&path)
+            .ok()
+            .and_then(|content| serde_yaml::from_str::<Value>(&content).ok());
+
+        match values {
+            Some(values) => {
+                merge_values(&mut output.values, values);
+                output.files.push(path);
+            }
+            None if ignore_missing => debug!("ignoring missing value file {}", path.display()),
+            None => output.missing.push(value_file.to_string()),
+        }
+    }
+
+    let inline_values = helm
+        .get("values")
+        .and_then(|v| v.as_str())
+        .and_then(|v| serde_yaml::from_str::<Value>(v).ok());
+
+    if let Some(values) = inline_values {
+        merge_values(&mut output.values, values);
+    }
+
+    let values_object = helm
+        .get("valuesObject")
+        .and_then(|v| serde_yaml::to_value(v).ok());
+
+    if let Some(values) = values_object {
+        merge_values(&mut output.values, values);
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use serde_json::json;
+
+    use crate::kubernetes::ApplicationSpec;
+
+    use super::{merge_values, resolve_values, Checkout, Checkouts};
+
+    #[test]
+    fn merge_values_deep() {
+        let mut base: serde_yaml::Value =
+            serde_yaml::from_str("image:\n  tag: 1.0\n  pullPolicy: Always\nreplicas: 1\n")
+                .expect("invalid yaml");
+        let overlay: serde_yaml::Value =
+            serde_yaml::from_str("image:\n  tag: 2.0\nreplicas: 3\n").expect("invalid yaml");
+
+        merge_values(&mut base, overlay);
+
+        let expected: serde_yaml::Value =
+            serde_yaml::from_str("image:\n  tag: 2.0\n  pullPolicy: Always\nreplicas: 3\n")
+                .expect("invalid yaml");
+
+        assert_eq!(expected, base);
+    }
+
+    #[test]
+    fn resolve_values_from_ref_source() {
+        let dir = tempfile::tempdir().expect("cannot create temp dir");
+        let checkout = dir.path();
+        fs::create_dir_all(checkout.join("loki")).expect("cannot create checkout");
+        fs::write(
+            checkout.join("loki/values.yaml"),
+            "loki:\n  auth_enabled: false\nreplicas: 1\n",
+        )
+        .expect("cannot write values");
+
+        let spec: ApplicationSpec = serde_json::from_value(json!({
+            "project": "default",
+            "sources": [
+                { "repoURL": "https://git.example.com/values.git", "targetRevision": "main", "ref": "values" },
+                {
+                    "chart": "loki",
+                    "repoURL": "https://grafana.github.io/helm-charts",
+                    "targetRevision": "5.0.0",
+                    "helm": {
+                        "valueFiles": ["$values/loki/values.yaml", "$values/loki/missing.yaml"],
+                        "valuesObject": { "replicas": 2 },
+                    },
+                },
+            ],
+        }))
+        .expect("invalid spec");
+
+        let checkouts = Checkouts {
+            default: checkout.join("unknown"),
+            repos: vec![
+                format!("https://git.example.com/values={}", checkout.display())
+                    .parse::<Checkout>()
+                    .expect("invalid checkout"),
+            ],
+        };
+
+        let result = resolve_values(&spec, &spec.sources.clone().unwrap()[1], &checkouts);
+
+        let expected: serde_yaml::Value =
+            serde_yaml::from_str("loki:\n  auth_enabled: false\nreplicas: 2\n")
+                .expect("invalid yaml");
+
+        assert_eq!(expected, result.values);
+        assert_eq!(vec![checkout.join("loki/values.yaml")], result.files);
+        assert_eq!(vec!["$values/loki/missing.yaml".to_owned()], result.missing);
+    }
+}
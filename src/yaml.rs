@@ -0,0 +1,468 @@
+use anyhow::bail;
+use serde_yaml::Value;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PathSegment {
+    Key(String),
+    Index(usize),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScalarSpan {
+    pub line: usize,
+    pub column: usize,
+    pub start: usize,
+    pub end: usize,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Line<'a> {
+    offset: usize,
+    text: &'a str,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Node {
+    line: usize,
+    column: usize,
+    end: usize,
+    scalar: bool,
+}
+
+fn split_lines(content: &str) -> Vec<Line<'_>> {
+    let mut output = Vec::new();
+    let mut offset = 0;
+
+    for text in content.split('\n') {
+        output.push(Line {
+            offset,
+            text: text.strip_suffix('\r').unwrap_or(text),
+        });
+
+        offset += text.len() + 1;
+    }
+
+    output
+}
+
+fn indent(text: &str) -> usize {
+    text.len() - text.trim_start_matches(' ').len()
+}
+
+fn is_ignorable(text: &str) -> bool {
+    let trimmed = text.trim();
+
+    trimmed.is_empty() || trimmed.starts_with('#')
+}
+
+fn is_document_marker(text: &str) -> bool {
+    (text.starts_with("---") || text.starts_with("..."))
+        && text[3..].chars().next().is_none_or(char::is_whitespace)
+}
+
+fn is_sequence_item(text: &str) -> bool {
+    text == "-" || text.starts_with("- ")
+}
+
+fn document_ranges(lines: &[Line]) -> Vec<(usize, usize)> {
+    let mut output = Vec::new();
+    let mut start = 0;
+    let mut explicit = false;
+
+    for (index, line) in lines.iter().enumerate() {
+        if !is_document_marker(line.text) {
+            continue;
+        }
+
+        let has_content = lines[start..index].iter().any(|l| !is_ignorable(l.text));
+
+        if explicit || has_content {
+            output.push((start, index));
+        }
+
+        start = index + 1;
+        explicit = line.text.starts_with("---");
+    }
+
+    let has_content = lines[start..].iter().any(|l| !is_ignorable(l.text));
+
+    if explicit || has_content {
+        output.push((start, lines.len()));
+    }
+
+    output
+}
+
+fn document_root(lines: &[Line], document: usize) -> Option<Node> {
+    let (start, end) = *document_ranges(lines).get(document)?;
+    let line = (start..end).find(|&l| !is_ignorable(lines[l].text))?;
+
+    Some(Node {
+        line,
+        column: indent(lines[line].text),
+        end,
+        scalar: false,
+    })
+}
+
+fn node_positions(lines: &[Line], node: &Node) -> Vec<usize> {
+    let mut output = vec![node.line];
+
+    output.extend((node.line + 1..node.end).filter(|&l| {
+        let text = lines[l].text;
+
+        !is_ignorable(text) && indent(text) == node.column
+    }));
+
+    output
+}
+
+fn parse_key(text: &str) -> Option<(String, usize)> {
+    let (key, rest) = match text.chars().next()? {
+        quote @ ('"' | '\'') => {
+            let close = text[1..].find(quote)? + 1;
+
+            (text[1..close].to_string(), close + 1)
+        }
+        _ => {
+            let colon = text
+                .match_indices(':')
+                .map(|(i, _)| i)
+                .find(|&i| text[i + 1..].chars().next().is_none_or(char::is_whitespace))?;
+
+            (text[..colon].trim_end().to_string(), colon)
+        }
+    };
+
+    if !text[rest..].starts_with(':') {
+        return None;
+    }
+
+    Some((key, rest + 1))
+}
+
+fn child_node(lines: &[Line], parent: &Node, line: usize, value_column: usize) -> Option<Node> {
+    let text = lines[line].text;
+    let inline = text[value_column..].trim_start();
+
+    if !inline.is_empty() && !inline.starts_with('#') {
+        return Some(Node {
+            line,
+            column: text.len() - inline.len(),
+            end: line + 1,
+            scalar: true,
+        });
+    }
+
+    let first = (line + 1..parent.end).find(|&l| !is_ignorable(lines[l].text))?;
+    let first_text = lines[first].text;
+    let column = indent(first_text);
+
+    let is_child = column > parent.column
+        || (column == parent.column && is_sequence_item(&first_text[column..]));
+
+    if !is_child {
+        return None;
+    }
+
+    let end = (first + 1..parent.end)
+        .find(|&l| {
+            let text = lines[l].text;
+            let i = indent(text);
+
+            !is_ignorable(text)
+                && (i < column
+                    || (i == column && column == parent.column && !is_sequence_item(&text[i..])))
+        })
+        .unwrap_or(parent.end);
+
+    Some(Node {
+        line: first,
+        column,
+        end,
+        scalar: false,
+    })
+}
+
+fn mapping_value(lines: &[Line], node: &Node, key: &str) -> Option<Node> {
+    for line in node_positions(lines, node) {
+        let text = &lines[line].text[node.column..];
+
+        if is_sequence_item(text) {
+            return None;
+        }
+
+        let (candidate, value_start) = match parse_key(text) {
+            Some(k) => k,
+            None => continue,
+        };
+
+        if candidate != key {
+            continue;
+        }
+
+        return child_node(lines, node, line, node.column + value_start);
+    }
+
+    None
+}
+
+fn sequence_item(lines: &[Line], node: &Node, index: usize) -> Option<Node> {
+    let items: Vec<usize> = node_positions(lines, node)
+        .into_iter()
+        .filter(|&l| is_sequence_item(&lines[l].text[node.column..]))
+        .collect();
+
+    let line = *items.get(index)?;
+    let end = items.get(index + 1).copied().unwrap_or(node.end);
+    let text = lines[line].text;
+    let content = text[node.column + 1..].trim_start();
+
+    if content.is_empty() || content.starts_with('#') {
+        let first = (line + 1..end).find(|&l| !is_ignorable(lines[l].text))?;
+
+        return Some(Node {
+            line: first,
+            column: indent(lines[first].text),
+            end,
+            scalar: false,
+        });
+    }
+
+    Some(Node {
+        line,
+        column: text.len() - content.len(),
+        end,
+        scalar: parse_key(content).is_none(),
+    })
+}
+
+fn scalar_length(text: &str) -> Option<usize> {
+    match text.chars().next()? {
+        '"' => {
+            let mut escaped = false;
+
+            for (i, c) in text.char_indices().skip(1) {
+                match c {
+                    '\\' if !escaped => escaped = true,
+                    '"' if !escaped => return Some(i + 1),
+                    _ => escaped = false,
+                }
+            }
+
+            None
+        }
+        '\'' => {
+            let mut chars = text.char_indices().skip(1).peekable();
+
+            while let Some((i, c)) = chars.next() {
+                if c != '\'' {
+                    continue;
+                }
+
+                if chars.peek().is_some_and(|(_, n)| *n == '\'') {
+                    chars.next();
+                    continue;
+                }
+
+                return Some(i + 1);
+            }
+
+            None
+        }
+        '|' | '>' | '[' | '{' | '&' | '*' | '!' => None,
+        _ => {
+            let end = text.find(" #").unwrap_or(text.len());
+
+            Some(text[..end].trim_end().len())
+        }
+    }
+}
+
+pub fn find_scalar(content: &str, document: usize, path: &[PathSegment]) -> Option<ScalarSpan> {
+    let lines = split_lines(content);
+    let mut node = document_root(&lines, document)?;
+
+    for segment in path {
+        node = match segment {
+            PathSegment::Key(key) => mapping_value(&lines, &node, key)?,
+            PathSegment::Index(index) => sequence_item(&lines, &node, *index)?,
+        };
+    }
+
+    if !node.scalar {
+        return None;
+    }
+
+    let line = lines[node.line];
+    let length = scalar_length(&line.text[node.column..])?;
+
+    Some(ScalarSpan {
+        line: node.line,
+        column: node.column,
+        start: line.offset + node.column,
+        end: line.offset + node.column + length,
+    })
+}
+
+fn quote_scalar(original: &str, value: &str) -> String {
+    if original.starts_with('\'') {
+        return format!("'{}'", value.replace('\'', "''"));
+    }
+
+    let is_plain_string =
+        matches!(serde_yaml::from_str::<Value>(value), Ok(Value::String(s)) if s == value);
+
+    if original.starts_with('"') || !is_plain_string {
+        return format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""));
+    }
+
+    value.to_string()
+}
+
+pub fn replace_scalar(
+    content: &str,
+    document: usize,
+    path: &[PathSegment],
+    value: &str,
+) -> anyhow::Result<String> {
+    let span = find_scalar(content, document, path);
+
+    if span.is_none() {
+        bail!("cannot find {:?} in document {}", path, document);
+    }
+
+    let span = span.unwrap();
+    let original = &content[span.start..span.end];
+
+    Ok(format!(
+        "{}{}{}",
+        &content[..span.start],
+        quote_scalar(original, value),
+        &content[span.end..]
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{find_scalar, replace_scalar, PathSegment};
+
+    const CONTENT: &str = "# root application
+apiVersion: argoproj.io/v1alpha1
+kind: Application
+metadata:
+  name: cert-manager
+spec:
+  project: default
+  source:
+    chart: cert-manager
+    repoURL: https://charts.jetstack.io
+    targetRevision: \"v1.12.0\" # pinned
+---
+apiVersion: argoproj.io/v1alpha1
+kind: Application
+metadata:
+  name: multi
+spec:
+  project: default
+  sources:
+  - repoURL: https://github.com/example/values.git
+    targetRevision: main
+    ref: values
+
+  # the chart itself
+  - chart: loki
+    repoURL: https://grafana.github.io/helm-charts
+    targetRevision: 5.0.0
+    helm:
+      valueFiles:
+        - $values/loki/values.yaml
+";
+
+    fn path(segments: &[&str]) -> Vec<PathSegment> {
+        segments
+            .iter()
+            .map(|s| match s.parse::<usize>() {
+                Ok(i) => PathSegment::Index(i),
+                Err(_) => PathSegment::Key(s.to_string()),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn find_scalar_single_source() {
+        let span = find_scalar(CONTENT, 0, &path(&["spec", "source", "targetRevision"]))
+            .expect("missing scalar");
+
+        assert_eq!(10, span.line);
+        assert_eq!("\"v1.12.0\"", &CONTENT[span.start..span.end]);
+    }
+
+    #[test]
+    fn find_scalar_multi_source() {
+        let span = find_scalar(
+            CONTENT,
+            1,
+            &path(&["spec", "sources", "1", "targetRevision"]),
+        )
+        .expect("missing scalar");
+
+        assert_eq!("5.0.0", &CONTENT[span.start..span.end]);
+
+        let span = find_scalar(
+            CONTENT,
+            1,
+            &path(&["spec", "sources", "0", "targetRevision"]),
+        )
+        .expect("missing scalar");
+
+        assert_eq!("main", &CONTENT[span.start..span.end]);
+    }
+
+    #[test]
+    fn find_scalar_missing_path() {
+        assert!(find_scalar(CONTENT, 0, &path(&["spec", "sources", "0"])).is_none());
+        assert!(find_scalar(CONTENT, 2, &path(&["spec"])).is_none());
+        assert!(find_scalar(CONTENT, 0, &path(&["spec", "source"])).is_none());
+    }
+
+    #[test]
+    fn replace_scalar_preserves_formatting() {
+        let result = replace_scalar(
+            CONTENT,
+            0,
+            &path(&["spec", "source", "targetRevision"]),
+            "v1.13.1",
+        )
+        .expect("cannot replace");
+
+        let result = replace_scalar(
+            &result,
+            1,
+            &path(&["spec", "sources", "1", "targetRevision"]),
+            "5.1.0",
+        )
+        .expect("cannot replace");
+
+        let expected = CONTENT
+            .replace("\"v1.12.0\" # pinned", "\"v1.13.1\" # pinned")
+            .replace("targetRevision: 5.0.0", "targetRevision: 5.1.0");
+
+        assert_eq!(expected, result);
+    }
+
+    #[test]
+    fn replace_scalar_quotes_non_string_values() {
+        let content = "spec:\n  source:\n    targetRevision: 1.9.0\n";
+
+        let result = replace_scalar(
+            content,
+            0,
+            &path(&["spec", "source", "targetRevision"]),
+            "1.10",
+        )
+        .expect("cannot replace");
+
+        assert_eq!("spec:\n  source:\n    targetRevision: \"1.10\"\n", result);
+    }
+}